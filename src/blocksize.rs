@@ -0,0 +1,471 @@
+use std::cmp::Ordering;
+use std::env;
+
+const POWER_LETTERS: [char; 9] = ['B', 'K', 'M', 'G', 'T', 'P', 'E', 'Z', 'Y'];
+
+/**
+ * how sizes are printed, from -B/--block-size, -k, -h, -H or the environment,
+ * see "Block size" in the coreutils manual
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockSize {
+    pub size: u64,          // bytes in an output block
+    pub autoscale: bool,    // -h/-H: every size with its own unit, e.g. 1.9G
+    pub base_1024: bool,    // units are powers of 1024, not 1000
+    pub unit: bool,         // the SIZE had no number, print the unit after each size, e.g. 38M
+    pub suffix_b: bool,     // the SIZE ended in B, e.g. MB or MiB
+    pub group_digits: bool, // the SIZE started with ', e.g. 1,234,567
+}
+
+impl Default for BlockSize {
+    fn default() -> BlockSize {
+        BlockSize::blocks(1024)
+    }
+}
+
+impl BlockSize {
+    /**
+     * plain blocks of `size` bytes
+     */
+    pub fn blocks(size: u64) -> BlockSize {
+        BlockSize {
+            size,
+            autoscale: false,
+            base_1024: false,
+            unit: false,
+            suffix_b: false,
+            group_digits: false,
+        }
+    }
+
+    /**
+     * -h with `base_1024`, -H without
+     */
+    pub fn human_readable(base_1024: bool) -> BlockSize {
+        BlockSize {
+            autoscale: true,
+            base_1024,
+            ..BlockSize::blocks(1)
+        }
+    }
+
+    /**
+     * parse a SIZE like 1K, 4096, M, MB, MiB, 'kB, human-readable or si
+     */
+    pub fn parse(spec: &str) -> Result<BlockSize, String> {
+        let invalid = || format!("invalid block size '{}'", spec);
+        let (group_digits, rest) = match spec.strip_prefix('\'') {
+            Some(rest) => (true, rest),
+            None => (false, spec),
+        };
+        let mut block_size = match rest {
+            "human-readable" => BlockSize::human_readable(true),
+            "si" => BlockSize::human_readable(false),
+            _ => {
+                let digits =
+                    rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+                let (number, suffix) = rest.split_at(digits);
+                let number: u64 = match number.is_empty() {
+                    true if suffix.is_empty() => return Err(invalid()),
+                    true => 1,
+                    false => number.parse().map_err(|_| invalid())?,
+                };
+                let mut chars = suffix.chars();
+                let (exponent, base) = match chars.next() {
+                    None => (0, 1024),
+                    Some(letter) => {
+                        let exponent = match letter {
+                            'k' | 'K' => 1,
+                            'm' | 'M' => 2,
+                            'g' | 'G' => 3,
+                            't' | 'T' => 4,
+                            'p' | 'P' => 5,
+                            'e' | 'E' => 6,
+                            'z' | 'Z' => 7,
+                            'y' | 'Y' => 8,
+                            _ => return Err(invalid()),
+                        };
+                        match chars.as_str() {
+                            "" | "iB" => (exponent, 1024),
+                            "B" => (exponent, 1000),
+                            _ => return Err(invalid()),
+                        }
+                    }
+                };
+                let size = (base as u64)
+                    .checked_pow(exponent)
+                    .and_then(|x| x.checked_mul(number))
+                    .ok_or_else(|| format!("block size '{}' is too large", spec))?;
+                if size == 0 {
+                    return Err(invalid());
+                }
+                // only a unit like M or MB: print it after the sizes too
+                let unit = digits == 0;
+                BlockSize {
+                    base_1024: unit && base == 1024,
+                    unit,
+                    suffix_b: unit && suffix.ends_with('B'),
+                    ..BlockSize::blocks(size)
+                }
+            }
+        };
+        block_size.group_digits = group_digits;
+        Ok(block_size)
+    }
+
+    /**
+     * the default without -B: DF_BLOCK_SIZE, BLOCK_SIZE or BLOCKSIZE,
+     * else 512 bytes with POSIXLY_CORRECT and 1K without. an invalid one is ignored
+     */
+    pub fn from_env() -> BlockSize {
+        BlockSize::from_vars(|name| env::var(name).ok())
+    }
+
+    fn from_vars<F: Fn(&str) -> Option<String>>(var: F) -> BlockSize {
+        ["DF_BLOCK_SIZE", "BLOCK_SIZE", "BLOCKSIZE"]
+            .iter()
+            .find_map(|name| var(name))
+            .and_then(|spec| BlockSize::parse(&spec).ok())
+            .unwrap_or_else(|| BlockSize::posix_from_vars(var))
+    }
+
+    /**
+     * the default of -P without -B: 512 bytes with POSIXLY_CORRECT and 1K without,
+     * DF_BLOCK_SIZE and the like don't count
+     */
+    pub fn posix() -> BlockSize {
+        BlockSize::posix_from_vars(|name| env::var(name).ok())
+    }
+
+    fn posix_from_vars<F: Fn(&str) -> Option<String>>(var: F) -> BlockSize {
+        match var("POSIXLY_CORRECT") {
+            Some(_) => BlockSize::blocks(512),
+            None => BlockSize::default(),
+        }
+    }
+
+    /**
+     * the header of the size column, e.g. 1K-blocks, 1MB-blocks, 512B-blocks or Size.
+     * the block size is shown in the base it is a round number of
+     */
+    pub fn header(&self) -> String {
+        if self.autoscale {
+            return "Size".to_string();
+        }
+        let (mut q1000, mut q1024) = (self.size, self.size);
+        let (mut by_1000, mut by_1024);
+        loop {
+            by_1000 = q1000.is_multiple_of(1000);
+            q1000 /= 1000;
+            by_1024 = q1024.is_multiple_of(1024);
+            q1024 /= 1024;
+            if !(by_1000 && by_1024) {
+                break;
+            }
+        }
+        let base_1024 = match by_1000 == by_1024 {
+            true => self.base_1024,
+            false => by_1024,
+        };
+        let suffix_b = self.suffix_b || !base_1024;
+        format!(
+            "{}-blocks",
+            scale(self.size as u128, base_1024, suffix_b, true)
+        )
+    }
+
+    /**
+     * the header of the size column of -P, the number of bytes like 1024-blocks
+     */
+    pub fn posix_header(&self) -> String {
+        match self.autoscale {
+            true => "Size".to_string(),
+            false => format!("{}-blocks", self.size),
+        }
+    }
+
+    /**
+     * a size given in bytes, in output blocks rounded up like coreutils does
+     */
+    pub fn show(&self, bytes: i128) -> String {
+        let sign = match bytes < 0 {
+            true => "-",
+            false => "",
+        };
+        if self.autoscale {
+            let power = match self.base_1024 {
+                true => 1024,
+                false => 1000,
+            };
+            return format!("{}{}", sign, human_readable(bytes.unsigned_abs(), power));
+        }
+        let blocks = bytes.unsigned_abs().div_ceil(self.size as u128).to_string();
+        let blocks = match self.group_digits {
+            true => group_digits(&blocks),
+            false => blocks,
+        };
+        let unit = match self.unit {
+            // what comes after the 1 of 1M-blocks
+            true => scale(self.size as u128, self.base_1024, self.suffix_b, false),
+            false => String::new(),
+        };
+        format!("{}{}{}", sign, blocks, unit)
+    }
+}
+
+/**
+ * n / d rounded to the nearest, a half to the even one like gnulib's human_readable
+ */
+fn div_round(n: u128, d: u128) -> u128 {
+    let (quotient, remainder) = (n / d, n % d);
+    match (remainder * 2).cmp(&d) {
+        Ordering::Greater => quotient + 1,
+        Ordering::Equal => quotient + quotient % 2,
+        Ordering::Less => quotient,
+    }
+}
+
+/**
+ * `size` bytes with the largest unit it fills, like 1K, 1.5M, 4kB or 512B,
+ * the header of -B. without `with_number` only the unit
+ */
+fn scale(size: u128, base_1024: bool, suffix_b: bool, with_number: bool) -> String {
+    let base: u128 = match base_1024 {
+        true => 1024,
+        false => 1000,
+    };
+    let mut exponent = 0;
+    while exponent + 1 < POWER_LETTERS.len() && size >= base.pow(exponent as u32 + 1) {
+        exponent += 1;
+    }
+    let mut unit = match exponent {
+        0 => String::new(),
+        1 if !base_1024 => "k".to_string(),
+        _ => POWER_LETTERS[exponent].to_string(),
+    };
+    if exponent != 0 && base_1024 && suffix_b {
+        unit.push('i');
+    }
+    if suffix_b {
+        unit.push('B');
+    }
+    if !with_number {
+        return unit;
+    }
+    let weight = base.pow(exponent as u32);
+    // one decimal below 10, rounded to the nearest, without a trailing .0
+    let number = match size / weight < 10 && !size.is_multiple_of(weight) {
+        true => {
+            let tenths = div_round(size * 10, weight);
+            match tenths % 10 {
+                0 => (tenths / 10).to_string(),
+                _ => format!("{}.{}", tenths / 10, tenths % 10),
+            }
+        }
+        false => div_round(size, weight).to_string(),
+    };
+    format!("{}{}", number, unit)
+}
+
+/**
+ * 1234567 -> 1,234,567
+ */
+fn group_digits(digits: &str) -> String {
+    let mut grouped = String::new();
+    for (i, c) in digits.chars().enumerate() {
+        if i != 0 && (digits.len() - i).is_multiple_of(3) {
+            grouped.push(',');
+        }
+        grouped.push(c);
+    }
+    grouped
+}
+
+// power should be 1000 or 1024
+pub fn human_readable(size: u128, power: u128) -> String {
+    let surfix = ["B", "K", "M", "G", "T", "P", "E", "Z", "Y"];
+
+    // short cut
+    if size == 0 {
+        return "0".to_string();
+    }
+    if size < power {
+        return format!("{}B", size);
+    }
+
+    // left.right<Unit>
+    // 1.9G etc.
+    // left
+    let mut i: u32 = 0;
+    while (i as usize) < surfix.len() - 1 && size >= power.pow(i + 1) {
+        i += 1;
+    }
+
+    let weight = power.pow(i);
+    let mut left = size / weight;
+
+    // right
+    let fraction = ((size % weight) as f64 / weight as f64) * 10f64;
+    let mut right = fraction.ceil() as u64;
+    if right >= 10 {
+        left += 1;
+        right = 0;
+    }
+
+    if left >= 10 {
+        // round up right
+        if right != 0 {
+            left += 1;
+        }
+        return format!("{}{}", left, surfix[i as usize]);
+    }
+
+    format!("{}.{}{}", left, right, surfix[i as usize])
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::blocksize::{group_digits, BlockSize};
+
+    #[test]
+    fn test_parse() {
+        let size = |spec: &str| BlockSize::parse(spec).map(|x| x.size);
+        assert_eq!(size("1K"), Ok(1024));
+        assert_eq!(size("4096"), Ok(4096));
+        assert_eq!(size("M"), Ok(1 << 20));
+        assert_eq!(size("kB"), Ok(1000));
+        assert_eq!(size("3MB"), Ok(3_000_000));
+        assert_eq!(size("2GiB"), Ok(2 << 30));
+        assert_eq!(size("'1"), Ok(1));
+        // every power letter in either case
+        for (i, letters) in ["kK", "mM", "gG", "tT", "pP", "eE"].iter().enumerate() {
+            for letter in letters.chars() {
+                assert_eq!(size(&letter.to_string()), Ok(1 << (10 * (i + 1))), "{}", letter);
+                assert_eq!(size(&format!("{}B", letter)), Ok(1000u64.pow(i as u32 + 1)));
+            }
+        }
+        // known but more than 64 bits
+        for spec in ["z", "Z", "y", "Y"] {
+            assert_eq!(
+                BlockSize::parse(spec),
+                Err(format!("block size '{}' is too large", spec))
+            );
+        }
+        for spec in ["", "0", "x", "1Q", "1KiBB", "-1", "'", "99999999999Y"] {
+            assert!(BlockSize::parse(spec).is_err(), "{}", spec);
+        }
+
+        let mib = BlockSize::parse("MiB").unwrap();
+        assert!(mib.unit && mib.base_1024 && mib.suffix_b);
+        assert!(!BlockSize::parse("1M").unwrap().unit);
+        assert!(BlockSize::parse("'K").unwrap().group_digits);
+        assert_eq!(
+            BlockSize::parse("human-readable"),
+            Ok(BlockSize::human_readable(true))
+        );
+        assert_eq!(BlockSize::parse("si"), Ok(BlockSize::human_readable(false)));
+    }
+
+    #[test]
+    fn test_from_vars() {
+        let from = |vars: &[(&str, &str)]| {
+            BlockSize::from_vars(|name| {
+                vars.iter()
+                    .find(|(x, _)| *x == name)
+                    .map(|(_, value)| value.to_string())
+            })
+            .size
+        };
+        assert_eq!(from(&[]), 1024);
+        assert_eq!(from(&[("POSIXLY_CORRECT", "1")]), 512);
+        assert_eq!(
+            from(&[("BLOCKSIZE", "M"), ("POSIXLY_CORRECT", "1")]),
+            1 << 20
+        );
+        assert_eq!(from(&[("BLOCK_SIZE", "4K"), ("DF_BLOCK_SIZE", "1")]), 1);
+        // an invalid one is ignored
+        assert_eq!(
+            from(&[("DF_BLOCK_SIZE", "bad"), ("BLOCK_SIZE", "4K")]),
+            1024
+        );
+    }
+
+    #[test]
+    fn test_posix_from_vars() {
+        let posix = |vars: &[(&str, &str)]| {
+            BlockSize::posix_from_vars(|name| {
+                vars.iter()
+                    .find(|(x, _)| *x == name)
+                    .map(|(_, value)| value.to_string())
+            })
+            .size
+        };
+        assert_eq!(posix(&[]), 1024);
+        assert_eq!(posix(&[("POSIXLY_CORRECT", "1")]), 512);
+        // only -B changes the size of -P
+        assert_eq!(posix(&[("DF_BLOCK_SIZE", "1M")]), 1024);
+        assert_eq!(posix(&[("BLOCK_SIZE", "4K"), ("BLOCKSIZE", "1")]), 1024);
+        assert_eq!(
+            posix(&[("DF_BLOCK_SIZE", "1M"), ("POSIXLY_CORRECT", "1")]),
+            512
+        );
+    }
+
+    #[test]
+    fn test_header() {
+        let header = |spec: &str| BlockSize::parse(spec).unwrap().header();
+        assert_eq!(header("1K"), "1K-blocks");
+        assert_eq!(header("K"), "1K-blocks");
+        assert_eq!(header("M"), "1M-blocks");
+        assert_eq!(header("1MB"), "1MB-blocks");
+        assert_eq!(header("1000"), "1kB-blocks");
+        assert_eq!(header("KiB"), "1KiB-blocks");
+        assert_eq!(header("512"), "512B-blocks");
+        assert_eq!(header("1"), "1B-blocks");
+        assert_eq!(header("4096"), "4K-blocks");
+        // rounded to the nearest, not up
+        assert_eq!(header("1536"), "1.5kB-blocks");
+        assert_eq!(header("1560"), "1.6kB-blocks");
+        assert_eq!(header("15400"), "15kB-blocks");
+        // a half to the even one
+        assert_eq!(header("1250"), "1.2kB-blocks");
+        assert_eq!(header("1350"), "1.4kB-blocks");
+        assert_eq!(header("si"), "Size");
+        assert_eq!(BlockSize::blocks(1024).posix_header(), "1024-blocks");
+        assert_eq!(BlockSize::blocks(512).posix_header(), "512-blocks");
+    }
+
+    #[test]
+    fn test_show() {
+        let show = |spec: &str, kilobytes: i128| {
+            BlockSize::parse(spec).unwrap().show(kilobytes << 10)
+        };
+        assert_eq!(show("1K", 1000), "1000");
+        // rounded up
+        assert_eq!(show("1M", 1025), "2");
+        assert_eq!(show("M", 2048), "2M");
+        assert_eq!(show("MB", 1000), "2MB");
+        assert_eq!(show("'1", 1234567), "1,264,196,608");
+        assert_eq!(show("1M", -1025), "-2");
+        assert_eq!(show("human-readable", 1536), "1.5M");
+        assert_eq!(show("si", -1), "-1.1K");
+        assert_eq!(
+            show("1", i64::MAX as i128),
+            (i64::MAX as u128 * 1024).to_string()
+        );
+        // no precision lost below 1K
+        let bytes = |spec: &str, bytes| BlockSize::parse(spec).unwrap().show(bytes);
+        assert_eq!(bytes("1", 512 * 1001), "512512");
+        assert_eq!(bytes("512", 512 * 1001), "1001");
+        assert_eq!(bytes("1K", 512 * 1001), "501");
+        assert_eq!(bytes("1", -512), "-512");
+    }
+
+    #[test]
+    fn test_group_digits() {
+        assert_eq!(group_digits("1"), "1");
+        assert_eq!(group_digits("123"), "123");
+        assert_eq!(group_digits("1234"), "1,234");
+        assert_eq!(group_digits("1234567"), "1,234,567");
+    }
+}
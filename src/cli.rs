@@ -1,5 +1,9 @@
-use clap::{App, Arg, SubCommand};
+use crate::blocksize::BlockSize;
+use clap::{value_t, App, Arg};
+use df::Filter;
 use std::collections::HashSet;
+use std::path::PathBuf;
+use std::time::Duration;
 
 #[derive(Default, Debug)]
 pub struct Options {
@@ -7,13 +11,27 @@ pub struct Options {
     pub show_all_fs: bool,
     pub listed_fs: HashSet<String>,
     pub excluded_fs: HashSet<String>,
-    pub human_readable: bool,
+    pub block_size: BlockSize, // from -B, -k, -h, -H or the environment
     pub print_grand_total: bool,
     pub field_list: Vec<String>,
-    pub human_readable_1024: bool, // true => show size in powers of 1024, false => powers of 1000
     pub inodes: bool,
     pub show_fs_type: bool,
     pub output_all_fields: bool,
+    pub mount_table: Option<PathBuf>, // read mounts from this file instead of /proc/self/mountinfo
+    pub sysroot: Option<PathBuf>, // read /proc and /sys of an offline host under this directory
+    pub pid: Option<u32>, // show the mounts in the mount namespace of this process
+    pub tree: bool,
+    pub propagation: Vec<String>, // only show mounts with one of these propagation types
+    pub with_opts: Vec<String>,    // only show mounts with all of these options
+    pub without_opts: Vec<String>, // hide mounts with any of these options
+    pub json: bool,
+    pub watch_mounts: bool, // print mount table changes instead of usage
+    pub files: Vec<PathBuf>, // only show the file systems these files are on
+    pub timeout: Option<Duration>, // give up on a mount whose statvfs takes longer
+    pub jobs: usize, // statvfs this many mounts at once
+    pub sync: bool,  // syncfs each mount before reading its usage
+    pub as_root: bool, // count the space reserved for root as available
+    pub portability: bool, // the POSIX output format of -P
 }
 
 impl Options {
@@ -21,15 +39,43 @@ impl Options {
         Options {
             show_local_fs: true,
             show_all_fs: false,
-            human_readable: true,
+            jobs: 1,
             ..Default::default()
         }
     }
+
+    /**
+     * the filesystems to show
+     */
+    pub fn filter(&self) -> Filter {
+        let mut filter = Filter::new()
+            .local(self.show_local_fs)
+            .all(self.show_all_fs)
+            .grand_total(self.print_grand_total);
+        for fs_type in self.listed_fs.iter() {
+            filter = filter.fs_type(fs_type);
+        }
+        for fs_type in self.excluded_fs.iter() {
+            filter = filter.exclude_type(fs_type);
+        }
+        for kind in self.propagation.iter() {
+            filter = filter.propagation(kind);
+        }
+        for opt in self.with_opts.iter() {
+            filter = filter.with_opt(opt);
+        }
+        for opt in self.without_opts.iter() {
+            filter = filter.without_opt(opt);
+        }
+        filter
+    }
 }
 
-pub fn parse_args() -> Options {
-    let mut options = Options::new();
-    let matches = App::new("df.rust")
+/**
+ * the command line of df
+ */
+fn app() -> App<'static, 'static> {
+    App::new("df.rust")
         .version("0.1.0")
         .author("Hedonihilist")
         .about("df written in Rust")
@@ -40,25 +86,27 @@ pub fn parse_args() -> Options {
                 .help("Show all file systems"),
         )
         .arg(
-            // core features first, ignore this option for now
             Arg::with_name("block_size")
                 .short("B")
                 .long("block-size")
                 .value_name("SIZE")
-                .help("scale sizes by size before printing them")
+                .help("scale sizes by SIZE before printing them; e.g., '-BM' prints sizes in units of 1,048,576 bytes")
                 .takes_value(true)
+                .overrides_with_all(&["size_in_k", "human_readable_1024", "human_readable_1000"])
         )
         .arg(
             Arg::with_name("human_readable_1024")
                 .short("h")
                 .long("human-readable")
-                .help("print sizes in powers of 1024"),
+                .help("print sizes in powers of 1024")
+                .overrides_with_all(&["block_size", "size_in_k", "human_readable_1000"]),
         )
         .arg(
             Arg::with_name("human_readable_1000")
                 .short("H")
                 .long("si")
-                .help("print sizes in powers of 1000"),
+                .help("print sizes in powers of 1000")
+                .overrides_with_all(&["block_size", "size_in_k", "human_readable_1024"]),
         )
         .arg(
             Arg::with_name("inodes")
@@ -67,10 +115,10 @@ pub fn parse_args() -> Options {
                 .help("list inode information instead of block usage")
         )
         .arg(
-            // ignore this for now
             Arg::with_name("size_in_k")
                 .short("k")
                 .help("like --block-size=1K")
+                .overrides_with_all(&["block_size", "human_readable_1024", "human_readable_1000"])
         )
         .arg(
             Arg::with_name("local")
@@ -105,6 +153,13 @@ pub fn parse_args() -> Options {
                 .multiple(true)
                 .number_of_values(1)
         )
+        .arg(
+            Arg::with_name("portability")
+                .short("P")
+                .long("portability")
+                .help("use the POSIX output format")
+                .conflicts_with_all(&["output", "output_all_fields", "tree", "json"])
+        )
         .arg(
             Arg::with_name("print_type")
                 .long("print-type")
@@ -120,11 +175,130 @@ pub fn parse_args() -> Options {
                 .multiple(true)
                 .number_of_values(1)
         )
-        .get_matches();
+        .arg(
+            Arg::with_name("mount_table")
+                .long("mount-table")
+                .value_name("FILE")
+                .help("read the mount table from FILE (mountinfo, /proc/mounts or /etc/mtab format), the usage of a table that is not /proc/self/mountinfo, /proc/mounts or /etc/mtab is only known from the saved stats of --sysroot")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("sysroot")
+                .long("sysroot")
+                .value_name("DIR")
+                .help("read /proc and /sys from DIR instead of the running system, usage from DIR/statvfs")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("pid")
+                .long("pid")
+                .value_name("PID")
+                .help("show the file systems in the mount namespace of process PID")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("tree")
+                .long("tree")
+                .help("show the mounts as a tree, each mount point under its parent")
+        )
+        .arg(
+            Arg::with_name("propagation")
+                .long("propagation")
+                .value_name("TYPE")
+                .help("limit listing to mounts with propagation TYPE")
+                .possible_values(&["shared", "slave", "master", "private", "unbindable"])
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+        )
+        .arg(
+            Arg::with_name("with_opt")
+                .long("with-opt")
+                .value_name("OPT")
+                .help("limit listing to mounts with option OPT, e.g. ro or errors=remount-ro")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+        )
+        .arg(
+            Arg::with_name("without_opt")
+                .long("without-opt")
+                .value_name("OPT")
+                .help("limit listing to mounts without option OPT")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+        )
+        .arg(
+            Arg::with_name("json")
+                .long("json")
+                .help("print the file systems as JSON")
+        )
+        .arg(
+            Arg::with_name("watch_mounts")
+                .long("watch-mounts")
+                .help("keep running and print mount, unmount and remount events as they happen")
+                .conflicts_with_all(&["mount_table", "sysroot", "tree", "json", "file"])
+        )
+        .arg(
+            Arg::with_name("timeout")
+                .long("timeout")
+                .value_name("SECONDS")
+                .help("give up on a file system that does not answer within SECONDS, e.g. a hung NFS mount")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("sync")
+                .long("sync")
+                .help("invoke sync before getting usage info")
+                .overrides_with("no_sync")
+        )
+        .arg(
+            Arg::with_name("no_sync")
+                .long("no-sync")
+                .help("do not invoke sync before getting usage info (default)")
+                .overrides_with("sync")
+        )
+        .arg(
+            Arg::with_name("as_root")
+                .long("as-root")
+                .help("count the space reserved for root as available, as a process of root sees it")
+        )
+        .arg(
+            Arg::with_name("jobs")
+                .short("j")
+                .long("jobs")
+                .value_name("N")
+                .help("stat up to N file systems at once, the output order does not change")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("file")
+                .value_name("FILE")
+                .help("show the file system each FILE is on")
+                .multiple(true)
+        )
+}
+
+pub fn parse_args() -> Options {
+    let mut options = Options::new();
+    let matches = app().get_matches();
     options.show_all_fs = matches.is_present("all");
-    options.human_readable =
-        matches.is_present("human_readable_1024") || matches.is_present("human_readable_1000");
-    options.human_readable_1024 = matches.is_present("human_readable_1024");
+    // the last of -B, -k, -h and -H wins
+    options.block_size = if matches.is_present("human_readable_1024") {
+        BlockSize::human_readable(true)
+    } else if matches.is_present("human_readable_1000") {
+        BlockSize::human_readable(false)
+    } else if matches.is_present("size_in_k") {
+        BlockSize::blocks(1024)
+    } else if let Some(spec) = matches.value_of("block_size") {
+        BlockSize::parse(spec).unwrap_or_else(|e| clap::Error::value_validation_auto(e).exit())
+    } else if matches.is_present("portability") {
+        BlockSize::posix()
+    } else {
+        BlockSize::from_env()
+    };
+    options.portability = matches.is_present("portability");
     options.inodes = matches.is_present("inodes");
     options.show_local_fs = matches.is_present("local");
 
@@ -141,6 +315,58 @@ pub fn parse_args() -> Options {
     options.print_grand_total = matches.is_present("total");
     options.show_fs_type = matches.is_present("print_type");
     options.output_all_fields = matches.is_present("output_all_fields");
+    options.mount_table = matches.value_of_os("mount_table").map(PathBuf::from);
+    options.sysroot = matches.value_of_os("sysroot").map(PathBuf::from);
+    options.tree = matches.is_present("tree");
+    options.json = matches.is_present("json");
+    options.watch_mounts = matches.is_present("watch_mounts");
+    options.sync = matches.is_present("sync");
+    options.as_root = matches.is_present("as_root");
+    if let Some(files) = matches.values_of_os("file") {
+        options.files = files.map(PathBuf::from).collect();
+    }
+    if matches.is_present("propagation") {
+        options.propagation = matches
+            .values_of("propagation")
+            .unwrap()
+            .map(|x| x.to_owned())
+            .collect();
+    }
+    if matches.is_present("with_opt") {
+        options.with_opts = matches
+            .values_of("with_opt")
+            .unwrap()
+            .map(|x| x.to_owned())
+            .collect();
+    }
+    if matches.is_present("without_opt") {
+        options.without_opts = matches
+            .values_of("without_opt")
+            .unwrap()
+            .map(|x| x.to_owned())
+            .collect();
+    }
+    if matches.is_present("timeout") {
+        let seconds = value_t!(matches, "timeout", f64).unwrap_or_else(|e| e.exit());
+        if !(seconds > 0.0 && seconds.is_finite()) {
+            clap::Error::value_validation_auto(format!(
+                "The timeout must be a positive number of seconds, not {}",
+                seconds
+            ))
+            .exit();
+        }
+        options.timeout = Some(Duration::from_secs_f64(seconds));
+    }
+    if matches.is_present("jobs") {
+        options.jobs = value_t!(matches, "jobs", usize).unwrap_or_else(|e| e.exit());
+        if options.jobs == 0 {
+            clap::Error::value_validation_auto("The number of jobs must be at least 1".to_string())
+                .exit();
+        }
+    }
+    if matches.is_present("pid") {
+        options.pid = Some(value_t!(matches, "pid", u32).unwrap_or_else(|e| e.exit()));
+    }
 
     if matches.is_present("fs_type") {
         options.listed_fs = matches
@@ -160,3 +386,40 @@ pub fn parse_args() -> Options {
 
     options
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::cli::app;
+    use clap::ErrorKind;
+
+    #[test]
+    fn test_portability_conflicts() {
+        let parse = |args: &[&str]| {
+            app()
+                .get_matches_from_safe(args.iter().copied())
+                .map(drop)
+                .map_err(|e| e.kind)
+        };
+        assert_eq!(parse(&["df", "-P"]), Ok(()));
+        assert_eq!(parse(&["df", "-P", "-BM", "--total", "/"]), Ok(()));
+        for conflict in [
+            "--output=source,target",
+            "--output-all-fields",
+            "--tree",
+            "--json",
+        ] {
+            assert_eq!(
+                parse(&["df", "-P", conflict]),
+                Err(ErrorKind::ArgumentConflict),
+                "{}",
+                conflict
+            );
+            assert_eq!(
+                parse(&["df", conflict, "--portability"]),
+                Err(ErrorKind::ArgumentConflict),
+                "{}",
+                conflict
+            );
+        }
+    }
+}
@@ -0,0 +1,203 @@
+use crate::fsclass::FsClassifier;
+use crate::mountinfo::MountInfo;
+use crate::usage::FsUsage;
+use std::collections::{HashMap, HashSet};
+use std::os::unix::ffi::OsStrExt;
+
+/**
+ * which filesystems df shows, built like
+ *
+ * Filter::new().local(true).fs_type("ext4").without_opt("ro")
+ *
+ * by default every local and remote filesystem with a size is shown, but no dummy one
+ */
+#[derive(Debug, Default, Clone)]
+pub struct Filter {
+    local: bool,
+    all: bool,
+    fs_types: HashSet<String>,
+    excluded_types: HashSet<String>,
+    propagation: Vec<String>,
+    with_opts: Vec<String>,
+    without_opts: Vec<String>,
+    grand_total: bool,
+}
+
+impl Filter {
+    pub fn new() -> Filter {
+        Filter::default()
+    }
+
+    /// like -l, hide remote filesystems
+    pub fn local(mut self, local: bool) -> Filter {
+        self.local = local;
+        self
+    }
+
+    /// like -a, show dummy and empty filesystems too
+    pub fn all(mut self, all: bool) -> Filter {
+        self.all = all;
+        self
+    }
+
+    /// like -t, only show this fs type (and the others given)
+    pub fn fs_type(mut self, fs_type: &str) -> Filter {
+        self.fs_types.insert(fs_type.to_string());
+        self
+    }
+
+    /// like -x, hide this fs type
+    pub fn exclude_type(mut self, fs_type: &str) -> Filter {
+        self.excluded_types.insert(fs_type.to_string());
+        self
+    }
+
+    /// only show mounts with this propagation type (or another one given)
+    pub fn propagation(mut self, kind: &str) -> Filter {
+        self.propagation.push(kind.to_string());
+        self
+    }
+
+    /// only show mounts with this option, see MountInfo::has_option
+    pub fn with_opt(mut self, opt: &str) -> Filter {
+        self.with_opts.push(opt.to_string());
+        self
+    }
+
+    /// hide mounts with this option
+    pub fn without_opt(mut self, opt: &str) -> Filter {
+        self.without_opts.push(opt.to_string());
+        self
+    }
+
+    /// like --total, keep remote filesystems of the same source apart
+    pub fn grand_total(mut self, grand_total: bool) -> Filter {
+        self.grand_total = grand_total;
+        self
+    }
+
+    /**
+     * whether `mount` passes the filter
+     */
+    pub fn matches(&self, mount: &MountInfo, classifier: &FsClassifier) -> bool {
+        if classifier.is_remote(mount) && self.local {
+            return false;
+        }
+        if classifier.is_dummy(mount) && !self.all && self.fs_types.is_empty() {
+            return false;
+        }
+        // fs_type not listed
+        if !self.fs_types.is_empty() && !self.fs_types.contains(&mount.fs_type)
+            || self.excluded_types.contains(&mount.fs_type)
+        {
+            return false;
+        }
+        if !self.propagation.is_empty()
+            && !self.propagation.iter().any(|x| mount.has_propagation(x))
+        {
+            return false;
+        }
+        self.with_opts.iter().all(|x| mount.has_option(x))
+            && !self.without_opts.iter().any(|x| mount.has_option(x))
+    }
+
+    /// like -a, whether dummy and empty filesystems are shown too
+    pub fn shows_all(&self) -> bool {
+        self.all
+    }
+
+    /**
+     * whether a filesystem with `usage` is shown, empty ones are hidden unless asked for
+     */
+    pub fn shows(&self, usage: &FsUsage) -> bool {
+        !usage.usage_known || usage.size != 0 || self.all || !self.fs_types.is_empty()
+    }
+
+    /**
+     * df.c中的filter_mountinfo_list的作用是去重，而不是根据输入的参数过滤掉mountinfo
+     * 真正的过滤在matches中
+     */
+    pub fn dedupe(&self, list: Vec<MountInfo>, classifier: &FsClassifier) -> Vec<MountInfo> {
+        let mut filtered: Vec<MountInfo> = vec![];
+        let mut seen: HashMap<u64, usize> = HashMap::new();
+        for me in list.into_iter() {
+            let mut discard_me: Option<usize> = None; //
+                                                      // skip
+            if !self.matches(&me, classifier) {
+                // pass
+            } else if me.dev_known() {
+                /*
+                在Linux中有一个bind mount的概念，能把一个目录挂载到另一个目录下，例如mount -o bind /boot/efi /tmp/bindmount
+                在df的输出中我们希望去除重复的设备
+                 */
+                if let Some(&idx) = seen.get(&me.dev()) {
+                    let seen_dev: &MountInfo = &filtered[idx];
+
+                    // target指当前me
+                    // source指
+                    let target_nearer_root =
+                        seen_dev.mount_point.as_os_str().len() > me.mount_point.as_os_str().len();
+                    let source_below_root = !seen_dev.root.as_os_str().is_empty()
+                        && !me.root.as_os_str().is_empty()
+                        && (seen_dev.root.as_os_str().len() < me.root.as_os_str().len());
+                    if !self.grand_total
+                        && classifier.is_remote(&me)
+                        && classifier.is_remote(seen_dev)
+                        && seen_dev.mount_source.eq(&me.mount_source)
+                    {
+                        // don't discard
+                    } else if (me.mount_source.as_bytes().contains(&b'/')
+                        && !seen_dev.mount_source.as_bytes().contains(&b'/'))
+                        || (target_nearer_root && !source_below_root)
+                        || (!seen_dev.mount_source.eq(&me.mount_source)
+                            && seen_dev.mount_point.eq(&me.mount_point))
+                    {
+                        // discard this one
+                        continue;
+                    } else {
+                        discard_me = Some(idx);
+                    }
+                }
+            }
+            if let Some(discard_idx) = discard_me {
+                filtered[discard_idx] = me;
+            } else {
+                let dev = me.dev();
+                filtered.push(me);
+                seen.insert(dev, filtered.len() - 1);
+            }
+        }
+        filtered
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::filter::Filter;
+    use crate::fsclass::FsClassifier;
+    use crate::mountinfo::table;
+
+    #[test]
+    fn test_matches() {
+        let classifier = FsClassifier::default();
+        let list = table(&[
+            "22 1 8:1 / / rw,relatime shared:1 - ext4 /dev/sda1 rw",
+            "23 22 0:21 / /proc rw,nosuid - proc proc rw",
+            "24 22 0:60 / /mnt/nfs ro shared:9 - nfs4 srv:/export rw",
+        ]);
+        let shown = |filter: &Filter| -> Vec<u32> {
+            list.iter()
+                .filter(|x| filter.matches(x, &classifier))
+                .map(|x| x.mount_id)
+                .collect()
+        };
+        assert_eq!(shown(&Filter::new()), [22, 24]);
+        assert_eq!(shown(&Filter::new().all(true)), [22, 23, 24]);
+        assert_eq!(shown(&Filter::new().local(true)), [22]);
+        assert_eq!(shown(&Filter::new().fs_type("proc")), [23]);
+        assert_eq!(shown(&Filter::new().exclude_type("nfs4")), [22]);
+        assert_eq!(shown(&Filter::new().with_opt("ro")), [24]);
+        assert_eq!(shown(&Filter::new().without_opt("ro")), [22]);
+        assert_eq!(shown(&Filter::new().all(true).propagation("private")), [23]);
+    }
+}
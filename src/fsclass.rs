@@ -0,0 +1,498 @@
+use crate::mountinfo::MountInfo;
+use crate::sysroot::Sysroot;
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::os::unix::ffi::OsStrExt;
+use std::path::PathBuf;
+
+/**
+ * pseudo filesystems newer than coreutils' ME_DUMMY list,
+ * used as well when /proc/filesystems can't be read
+ */
+const PSEUDO_FS_TYPES: [&str; 15] = [
+    "bdev",
+    "binfmt_misc",
+    "bpf",
+    "cgroup",
+    "cgroup2",
+    "configfs",
+    "cpuset",
+    "efivarfs",
+    "nsfs",
+    "pipefs",
+    "pstore",
+    "securityfs",
+    "selinuxfs",
+    "sockfs",
+    "tracefs",
+];
+
+/**
+ * "nodev" filesystems that still hold data worth listing: memory, overlay and
+ * stacked filesystems, network and cluster filesystems and the shared folders of
+ * virtual machines. an empty one is still left out by its zero size, like in coreutils
+ */
+const NODEV_STORAGE_FS_TYPES: [&str; 32] = [
+    "tmpfs",
+    "devtmpfs",
+    "ramfs",
+    "hugetlbfs",
+    "overlay",
+    "aufs",
+    "shiftfs",
+    "ecryptfs",
+    "zfs",
+    "fuse",
+    "nfs",
+    "nfs4",
+    "cifs",
+    "smb3",
+    "smbfs",
+    "ncpfs",
+    "9p",
+    "virtiofs",
+    "ceph",
+    "afs",
+    "coda",
+    "lustre",
+    "orangefs",
+    "pvfs2",
+    "beegfs",
+    "gpfs",
+    "glusterfs",
+    "vboxsf",
+    "vmhgfs",
+    "prl_fs",
+    "drvfs",
+    "sdcardfs",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FsClass {
+    Remote,
+    /// neither remote nor dummy
+    Local,
+    Dummy,
+}
+
+/**
+ * a user rule from the config file, e.g.
+ *
+ * [[rule]]
+ * fstype = "fuse.glusterfs"
+ * class = "remote"
+ *
+ * fstype, source and target are globs on the fs type, the mount source and the
+ * mount point. a rule applies when all of those it has match
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClassRule {
+    pub fstype: Option<String>,
+    pub source: Option<String>,
+    pub target: Option<String>,
+    pub class: FsClass,
+}
+
+impl ClassRule {
+    pub fn matches(&self, mount: &MountInfo) -> bool {
+        let fits = |pattern: &Option<String>, text: &[u8]| {
+            pattern
+                .as_ref()
+                .is_none_or(|pattern| glob_match(pattern.as_bytes(), text))
+        };
+        fits(&self.fstype, mount.fs_type.as_bytes())
+            && fits(&self.source, mount.mount_source.as_bytes())
+            && fits(&self.target, mount.mount_point.as_os_str().as_bytes())
+    }
+}
+
+/**
+ * shell-like glob: * and ? match any bytes including '/', [a-z] and [!a-z] match a set,
+ * a backslash makes the next byte literal
+ */
+fn glob_match(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => text.is_empty(),
+        Some((b'*', rest)) => (0..=text.len()).any(|i| glob_match(rest, &text[i..])),
+        Some((b'?', rest)) => !text.is_empty() && glob_match(rest, &text[1..]),
+        Some((b'[', rest)) => {
+            let end = match rest.iter().skip(1).position(|&b| b == b']') {
+                Some(end) => end + 1,
+                // no closing bracket, '[' is literal
+                None => return text.first() == Some(&b'[') && glob_match(rest, &text[1..]),
+            };
+            let (negate, set) = match rest[0] {
+                b'!' | b'^' => (true, &rest[1..end]),
+                _ => (false, &rest[..end]),
+            };
+            let c = match text.first() {
+                Some(&c) => c,
+                None => return false,
+            };
+            let mut found = false;
+            let mut i = 0;
+            while i < set.len() {
+                if i + 2 < set.len() && set[i + 1] == b'-' {
+                    found |= set[i] <= c && c <= set[i + 2];
+                    i += 3;
+                } else {
+                    found |= set[i] == c;
+                    i += 1;
+                }
+            }
+            found != negate && glob_match(&rest[end + 1..], &text[1..])
+        }
+        Some((b'\\', rest)) if !rest.is_empty() => {
+            text.first() == Some(&rest[0]) && glob_match(&rest[1..], &text[1..])
+        }
+        Some((&p, rest)) => text.first() == Some(&p) && glob_match(rest, &text[1..]),
+    }
+}
+
+/**
+ * parse the [[rule]] entries of a config file
+ */
+pub fn parse_rules(content: &str) -> Result<Vec<ClassRule>, String> {
+    let config: toml::Value = content.parse().map_err(|e| format!("{}", e))?;
+    let rules = match config.get("rule") {
+        Some(toml::Value::Array(rules)) => rules,
+        Some(_) => return Err("\"rule\" must be an array of tables ([[rule]])".to_string()),
+        None => return Ok(vec![]),
+    };
+    let mut parsed = vec![];
+    for (i, rule) in rules.iter().enumerate() {
+        let table = rule
+            .as_table()
+            .ok_or_else(|| format!("rule {}: not a table", i + 1))?;
+        let mut fstype = None;
+        let mut source = None;
+        let mut target = None;
+        let mut class = None;
+        for (key, value) in table.iter() {
+            let value = value
+                .as_str()
+                .ok_or_else(|| format!("rule {}: {} must be a string", i + 1, key))?
+                .to_string();
+            match key.as_str() {
+                "fstype" => fstype = Some(value),
+                "source" => source = Some(value),
+                "target" => target = Some(value),
+                "class" => {
+                    class = Some(match value.as_str() {
+                        "remote" => FsClass::Remote,
+                        "local" => FsClass::Local,
+                        "dummy" => FsClass::Dummy,
+                        _ => return Err(format!("rule {}: unknown class \"{}\"", i + 1, value)),
+                    })
+                }
+                _ => return Err(format!("rule {}: unknown key \"{}\"", i + 1, key)),
+            }
+        }
+        let class = class.ok_or_else(|| format!("rule {}: missing class", i + 1))?;
+        if fstype.is_none() && source.is_none() && target.is_none() {
+            return Err(format!("rule {}: needs fstype, source or target", i + 1));
+        }
+        parsed.push(ClassRule {
+            fstype,
+            source,
+            target,
+            class,
+        });
+    }
+    Ok(parsed)
+}
+
+/**
+ * /etc/df.rust.toml, then the user's $XDG_CONFIG_HOME/df.rust/classes.toml
+ * (~/.config/df.rust/classes.toml), so the user's rules come last and win
+ */
+fn config_paths() -> Vec<PathBuf> {
+    let mut paths = vec![PathBuf::from("/etc/df.rust.toml")];
+    let config_dir = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")));
+    if let Some(dir) = config_dir {
+        paths.push(dir.join("df.rust/classes.toml"));
+    }
+    paths
+}
+
+/**
+ * the rules of every config file, a file with a bad rule is ignored with a warning
+ */
+fn load_rules(warnings: &mut Vec<String>) -> Vec<ClassRule> {
+    let mut rules = vec![];
+    for path in config_paths() {
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(_) => continue,
+        };
+        match parse_rules(&content) {
+            Ok(parsed) => rules.extend(parsed),
+            Err(e) => warnings.push(format!("{}: {}, ignored", path.display(), e)),
+        }
+    }
+    rules
+}
+
+/**
+ * tells remote and pseudo filesystems from local ones.
+ * a type the running kernel marks "nodev" in /proc/filesystems is a pseudo
+ * filesystem unless it is known to store data (tmpfs, nfs, ...).
+ * user rules from the config files come first, the last matching rule wins
+ */
+#[derive(Debug, Default)]
+pub struct FsClassifier {
+    nodev: HashSet<String>,
+    rules: Vec<ClassRule>,
+}
+
+impl FsClassifier {
+    /**
+     * read /proc/filesystems of the inspected host and the rules of the config files,
+     * config files that can not be parsed go to `warnings`
+     */
+    pub fn load(sysroot: &Sysroot, warnings: &mut Vec<String>) -> FsClassifier {
+        let classifier = match fs::read_to_string(sysroot.path("/proc/filesystems")) {
+            Ok(content) => FsClassifier::parse(&content),
+            Err(_) => FsClassifier::default(),
+        };
+        classifier.with_rules(load_rules(warnings))
+    }
+
+    /**
+     * content is in the /proc/filesystems format: "nodev\tsysfs" or "\text4"
+     */
+    pub fn parse(content: &str) -> FsClassifier {
+        let nodev = content
+            .lines()
+            .filter_map(|line| line.strip_prefix("nodev"))
+            .map(|fs_type| fs_type.trim().to_string())
+            .filter(|fs_type| !fs_type.is_empty())
+            .collect();
+        FsClassifier {
+            nodev,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_rules(mut self, rules: Vec<ClassRule>) -> FsClassifier {
+        self.rules = rules;
+        self
+    }
+
+    fn user_class(&self, mount: &MountInfo) -> Option<FsClass> {
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| rule.matches(mount))
+            .map(|rule| rule.class)
+    }
+
+    pub fn is_remote(&self, mount: &MountInfo) -> bool {
+        match self.user_class(mount) {
+            Some(FsClass::Remote) => true,
+            Some(FsClass::Local) => false,
+            _ => mount.is_remote(),
+        }
+    }
+
+    pub fn is_dummy(&self, mount: &MountInfo) -> bool {
+        match self.user_class(mount) {
+            Some(FsClass::Dummy) => return true,
+            Some(FsClass::Remote) | Some(FsClass::Local) => return false,
+            None => {}
+        }
+        if mount.is_dummy() {
+            return true;
+        }
+        // fuse.sshfs is listed as fuse
+        let base_type = mount.fs_type.split('.').next().unwrap_or("");
+        if NODEV_STORAGE_FS_TYPES.contains(&base_type) {
+            return false;
+        }
+        PSEUDO_FS_TYPES.contains(&base_type) || self.nodev.contains(base_type)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fsclass::{glob_match, parse_rules, ClassRule, FsClass, FsClassifier};
+    use crate::mountinfo::MountInfo;
+
+    fn mount(fs_type: &str) -> MountInfo {
+        MountInfo {
+            fs_type: fs_type.to_string(),
+            mount_source: fs_type.into(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_proc_filesystems() {
+        let classifier = FsClassifier::parse(
+            "nodev\tsysfs\nnodev\ttmpfs\nnodev\tdebugfs\nnodev\tramfs\nnodev\tfuse\n\text4\nnodev\tbrandnewfs\n\tvfat\nnodev\tnfs4\n",
+        );
+        assert!(classifier.is_dummy(&mount("sysfs")));
+        assert!(classifier.is_dummy(&mount("brandnewfs")));
+        assert!(!classifier.is_dummy(&mount("ext4")));
+        assert!(!classifier.is_dummy(&mount("vfat")));
+        assert!(!classifier.is_dummy(&mount("tmpfs")));
+        assert!(!classifier.is_dummy(&mount("ramfs")));
+        assert!(!classifier.is_dummy(&mount("nfs4")));
+        assert!(!classifier.is_dummy(&mount("fuse.sshfs")));
+        // fuse.portal is on the ME_DUMMY list
+        assert!(classifier.is_dummy(&mount("fuse.portal")));
+    }
+
+    #[test]
+    fn test_without_proc_filesystems() {
+        let classifier = FsClassifier::default();
+        for fs_type in [
+            "cgroup2",
+            "bpf",
+            "tracefs",
+            "securityfs",
+            "pstore",
+            "configfs",
+            "efivarfs",
+            "nsfs",
+            "binfmt_misc",
+            "proc",
+            "devpts",
+        ] {
+            assert!(classifier.is_dummy(&mount(fs_type)), "{}", fs_type);
+        }
+        for fs_type in ["ext4", "xfs", "tmpfs", "overlay", "btrfs"] {
+            assert!(!classifier.is_dummy(&mount(fs_type)), "{}", fs_type);
+        }
+    }
+
+    #[test]
+    fn test_nodev_storage() {
+        let storage = [
+            "ecryptfs",
+            "vboxsf",
+            "orangefs",
+            "beegfs",
+            "gpfs",
+            "hugetlbfs",
+        ];
+        let proc_filesystems: String = storage
+            .iter()
+            .map(|fs_type| format!("nodev\t{}\n", fs_type))
+            .collect();
+        // data filesystems even though the kernel marks them nodev
+        for classifier in [
+            FsClassifier::parse(&proc_filesystems),
+            FsClassifier::default(),
+        ] {
+            for fs_type in storage {
+                assert!(!classifier.is_dummy(&mount(fs_type)), "{}", fs_type);
+            }
+        }
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match(b"/var/lib/docker/*", b"/var/lib/docker/overlay2/abc/merged"));
+        assert!(!glob_match(b"/var/lib/docker/*", b"/var/lib/dockerd"));
+        assert!(glob_match(b"fuse.*", b"fuse.glusterfs"));
+        assert!(glob_match(b"nfs?", b"nfs4"));
+        assert!(!glob_match(b"nfs?", b"nfs"));
+        assert!(glob_match(b"/dev/sd[a-c]1", b"/dev/sdb1"));
+        assert!(!glob_match(b"/dev/sd[!a-c]1", b"/dev/sdb1"));
+        assert!(glob_match(b"a\\*b", b"a*b"));
+        assert!(!glob_match(b"a\\*b", b"axb"));
+        assert!(glob_match(b"[x", b"[x"));
+        assert!(glob_match(b"*", b""));
+    }
+
+    #[test]
+    fn test_parse_rules() {
+        let rules = parse_rules(
+            r#"
+[[rule]]
+fstype = "ceph"
+class = "remote"
+
+[[rule]]
+source = "//nas/*"
+target = "/mnt/*"
+class = "local"
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            rules,
+            [
+                ClassRule {
+                    fstype: Some("ceph".to_string()),
+                    source: None,
+                    target: None,
+                    class: FsClass::Remote
+                },
+                ClassRule {
+                    fstype: None,
+                    source: Some("//nas/*".to_string()),
+                    target: Some("/mnt/*".to_string()),
+                    class: FsClass::Local
+                }
+            ]
+        );
+        assert!(parse_rules("").unwrap().is_empty());
+        assert!(parse_rules("[[rule]]\nfstype = \"9p\"\n").is_err());
+        assert!(parse_rules("[[rule]]\nclass = \"dummy\"\n").is_err());
+        assert!(parse_rules("[[rule]]\nfstype = \"9p\"\nclass = \"fast\"\n").is_err());
+        assert!(parse_rules("[[rule]]\nfs = \"9p\"\nclass = \"local\"\n").is_err());
+        assert!(parse_rules("rule = 1").is_err());
+    }
+
+    #[test]
+    fn test_user_rules() {
+        let rules = parse_rules(
+            r#"
+[[rule]]
+fstype = "9p"
+class = "remote"
+
+[[rule]]
+fstype = "virtiofs"
+class = "remote"
+
+[[rule]]
+target = "/mnt/shared/*"
+class = "local"
+
+[[rule]]
+fstype = "ext4"
+target = "/var/lib/docker/*"
+class = "dummy"
+
+[[rule]]
+fstype = "tracefs"
+class = "local"
+"#,
+        )
+        .unwrap();
+        let classifier = FsClassifier::default().with_rules(rules);
+        let mount = |fs_type: &str, source: &str, target: &str| MountInfo {
+            fs_type: fs_type.to_string(),
+            mount_source: source.into(),
+            mount_point: target.into(),
+            ..Default::default()
+        };
+        assert!(classifier.is_remote(&mount("9p", "hostshare", "/mnt/host")));
+        assert!(classifier.is_remote(&mount("virtiofs", "myfs", "/mnt/v")));
+        // the later rule wins
+        assert!(!classifier.is_remote(&mount("9p", "hostshare", "/mnt/shared/a")));
+        assert!(!classifier.is_remote(&mount("nfs4", "srv:/x", "/mnt/shared/b")));
+        assert!(classifier.is_remote(&mount("nfs4", "srv:/x", "/mnt/nfs")));
+        assert!(classifier.is_dummy(&mount("ext4", "/dev/sda1", "/var/lib/docker/x")));
+        assert!(!classifier.is_dummy(&mount("ext4", "/dev/sda1", "/var/lib/other")));
+        assert!(!classifier.is_dummy(&mount("tracefs", "tracefs", "/sys/kernel/tracing")));
+        assert!(classifier.is_dummy(&mount("bpf", "bpf", "/sys/fs/bpf")));
+    }
+}
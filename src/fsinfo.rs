@@ -0,0 +1,239 @@
+use crate::fsstat::{with_timeout, FsStat, StatError};
+use nix::sys::statfs::statfs;
+use nix::sys::statvfs::FsFlags;
+use std::path::Path;
+use std::time::Duration;
+
+/**
+ * magic numbers of statfs(2) f_type and the fs types of mountinfo they stand for,
+ * the first one is the name shown. from linux/magic.h and the filesystems' sources
+ */
+const MAGICS: [(u64, &[&str]); 52] = [
+    (0xef53, &["ext2/ext3", "ext2", "ext3", "ext4"]),
+    (0x58465342, &["xfs"]),
+    (0x9123683e, &["btrfs"]),
+    (0xf2f52010, &["f2fs"]),
+    (0x3153464a, &["jfs"]),
+    (0x52654973, &["reiserfs"]),
+    (0x2fc12fc1, &["zfs"]),
+    (0x4d44, &["msdos", "vfat", "fat"]),
+    (0x2011bab0, &["exfat"]),
+    (0x5346544e, &["ntfs", "ntfs3"]),
+    (0x9660, &["isofs", "iso9660"]),
+    (0x15013346, &["udf"]),
+    (0x73717368, &["squashfs"]),
+    (0x28cd3d45, &["cramfs"]),
+    (0x7275, &["romfs"]),
+    (0xe0f5e1e2, &["erofs"]),
+    (0x24051905, &["ubifs"]),
+    (0x01161970, &["gfs2"]),
+    (0x7461636f, &["ocfs2"]),
+    (0x01021994, &["tmpfs", "devtmpfs"]),
+    (0x858458f6, &["ramfs", "devtmpfs"]),
+    (0x958458f6, &["hugetlbfs"]),
+    (0x794c7630, &["overlay", "overlayfs"]),
+    (0x61756673, &["aufs"]),
+    (0x65735546, &["fuse", "fuseblk", "virtiofs"]),
+    (0x65735543, &["fusectl"]),
+    (0x6969, &["nfs", "nfs4"]),
+    (0xff534d42, &["cifs", "smb3", "smbfs"]),
+    (0xfe534d42, &["smb2", "cifs", "smb3"]),
+    (0x01021997, &["v9fs", "9p"]),
+    (0x00c36400, &["ceph"]),
+    (0x0bd00bd0, &["lustre"]),
+    (0x47504653, &["gpfs"]),
+    (0x5346414f, &["afs", "auristorfs"]),
+    (0x0187, &["autofs"]),
+    (0x9fa0, &["proc"]),
+    (0x62656572, &["sysfs"]),
+    (0x1cd1, &["devpts"]),
+    (0x27e0eb, &["cgroup"]),
+    (0x63677270, &["cgroup2"]),
+    (0x64626720, &["debugfs"]),
+    (0x74726163, &["tracefs"]),
+    (0x73636673, &["securityfs"]),
+    (0xf97cff8c, &["selinuxfs"]),
+    (0xcafe4a11, &["bpf"]),
+    (0x6e736673, &["nsfs"]),
+    (0x19800202, &["mqueue"]),
+    (0x42494e4d, &["binfmt_misc"]),
+    (0x62656570, &["configfs"]),
+    (0xde5e81e4, &["efivarfs"]),
+    (0x6165676c, &["pstore"]),
+    (0x67596969, &["rpc_pipefs"]),
+];
+
+/**
+ * ST_* flags of statvfs(3) f_flag
+ */
+const FLAGS: [(FsFlags, &str); 10] = [
+    (FsFlags::ST_RDONLY, "rdonly"),
+    (FsFlags::ST_NOSUID, "nosuid"),
+    (FsFlags::ST_NODEV, "nodev"),
+    (FsFlags::ST_NOEXEC, "noexec"),
+    (FsFlags::ST_SYNCHRONOUS, "synchronous"),
+    (FsFlags::ST_MANDLOCK, "mandlock"),
+    (FsFlags::ST_WRITE, "write"),
+    (FsFlags::ST_APPEND, "append"),
+    (FsFlags::ST_NOATIME, "noatime"),
+    (FsFlags::ST_NODIRATIME, "nodiratime"),
+];
+
+/**
+ * what statfs(2) tells beyond the usage: the kernel's idea of the fs type,
+ * the filesystem ID, the limits and the ST_* mount flags
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FsInfo {
+    pub magic: u64,
+    pub fsid: u64,
+    pub block_size: u64,
+    pub fragment_size: u64,
+    pub name_max: u64,
+    pub flags: FsFlags,
+}
+
+impl FsInfo {
+    /**
+     * statfs `path`, whose statvfs is `stat`: the type and the limits come from statfs,
+     * the filesystem ID and the flags from the statvfs of the usage
+     */
+    // __fsword_t is not 64 bits on every target
+    #[allow(clippy::unnecessary_cast)]
+    pub fn statfs(path: &Path, stat: &FsStat) -> nix::Result<FsInfo> {
+        let buf = statfs(path)?;
+        Ok(FsInfo {
+            // f_type is signed on some targets, the magic is 32 bits
+            magic: buf.filesystem_type().0 as u64 & 0xffff_ffff,
+            // the two words of f_fsid the other way round, like stat -f %i
+            fsid: stat.fsid.rotate_left(32),
+            block_size: buf.block_size() as u64,
+            fragment_size: stat.fragment_size,
+            name_max: buf.maximum_name_length() as u64,
+            flags: stat.flags,
+        })
+    }
+
+    /**
+     * statfs on a worker thread, giving up after `timeout` if there is one
+     */
+    pub fn statfs_timeout(
+        path: &Path,
+        stat: &FsStat,
+        timeout: Option<Duration>,
+    ) -> Result<FsInfo, StatError> {
+        match timeout {
+            Some(timeout) => {
+                let (path, stat) = (path.to_path_buf(), *stat);
+                with_timeout(timeout, move || FsInfo::statfs(&path, &stat))
+            }
+            None => Ok(FsInfo::statfs(path, stat)?),
+        }
+    }
+
+    fn known_types(&self) -> Option<&'static [&'static str]> {
+        MAGICS
+            .iter()
+            .find(|(magic, _)| *magic == self.magic)
+            .map(|(_, types)| *types)
+    }
+
+    /**
+     * the fs type of the magic number, e.g. "xfs", or the number when it is not known
+     */
+    pub fn magic_name(&self) -> String {
+        match self.known_types() {
+            Some(types) => types[0].to_string(),
+            None => format!("{:#x}", self.magic),
+        }
+    }
+
+    /**
+     * false when the magic number is known and belongs to another type than `fs_type`.
+     * FUSE subtypes like fuse.sshfs are FUSE
+     */
+    pub fn matches_type(&self, fs_type: &str) -> bool {
+        let base = fs_type.split('.').next().unwrap_or(fs_type);
+        match self.known_types() {
+            Some(types) => types.contains(&fs_type) || types.contains(&base),
+            None => true,
+        }
+    }
+
+    /**
+     * the ST_* flags, e.g. "rdonly,nosuid", "-" when none is set
+     */
+    pub fn flag_names(&self) -> String {
+        let names: Vec<&str> = FLAGS
+            .iter()
+            .filter(|(flag, _)| self.flags.contains(*flag))
+            .map(|(_, name)| *name)
+            .collect();
+        match names.is_empty() {
+            true => "-".to_string(),
+            false => names.join(","),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fsinfo::{FsInfo, MAGICS};
+    use crate::fsstat::FsStat;
+    use nix::sys::statvfs::{statvfs, FsFlags};
+    use std::collections::HashSet;
+    use std::path::Path;
+
+    fn info(magic: u64, flags: FsFlags) -> FsInfo {
+        FsInfo {
+            magic,
+            fsid: 0,
+            block_size: 4096,
+            fragment_size: 4096,
+            name_max: 255,
+            flags,
+        }
+    }
+
+    #[test]
+    fn test_magic() {
+        let magics: HashSet<u64> = MAGICS.iter().map(|(magic, _)| *magic).collect();
+        assert_eq!(magics.len(), MAGICS.len());
+
+        let ext4 = info(0xef53, FsFlags::empty());
+        assert_eq!(ext4.magic_name(), "ext2/ext3");
+        assert!(ext4.matches_type("ext4"));
+        assert!(!ext4.matches_type("xfs"));
+        let fuse = info(0x65735546, FsFlags::empty());
+        assert!(fuse.matches_type("fuse.sshfs"));
+        assert!(!fuse.matches_type("overlay"));
+        let unknown = info(0x1234, FsFlags::empty());
+        assert_eq!(unknown.magic_name(), "0x1234");
+        assert!(unknown.matches_type("anything"));
+    }
+
+    #[test]
+    fn test_flags() {
+        let ro = info(0xef53, FsFlags::ST_RDONLY | FsFlags::ST_NOSUID);
+        assert_eq!(ro.flag_names(), "rdonly,nosuid");
+        assert_eq!(info(0xef53, FsFlags::empty()).flag_names(), "-");
+    }
+
+    #[test]
+    fn test_statfs() {
+        let info = |path: &str| FsInfo::statfs(Path::new(path), &FsStat::stat(path).unwrap());
+        let proc = info("/proc").unwrap();
+        assert_eq!(proc.magic_name(), "proc");
+        assert!(proc.matches_type("proc"));
+        assert!(proc.name_max > 0);
+        let root = info("/").unwrap();
+        assert_eq!(root.flags, statvfs("/").unwrap().flags());
+        assert_eq!(proc.flags, statvfs("/proc").unwrap().flags());
+        assert_eq!(
+            root.fsid.rotate_left(32),
+            statvfs("/").unwrap().filesystem_id() as u64
+        );
+        let none = FsStat::default();
+        assert!(FsInfo::statfs(Path::new("/no/such/dir"), &none).is_err());
+    }
+}
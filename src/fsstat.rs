@@ -0,0 +1,300 @@
+use crate::mountinfo::unescape;
+use nix::errno::Errno;
+use nix::fcntl::{open, OFlag};
+use nix::libc;
+use nix::sys::stat::Mode;
+use nix::sys::statvfs::{statvfs, FsFlags, Statvfs};
+use nix::unistd::close;
+use nix::NixPath;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatError {
+    /// statvfs failed
+    Errno(Errno),
+    /// statvfs did not return in time, e.g. on an NFS mount whose server is gone
+    Timeout,
+}
+
+/**
+ * why statvfs failed, as far as df cares
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatErrorKind {
+    /// EACCES or EPERM, e.g. a FUSE mount of another user
+    PermissionDenied,
+    /// ENOENT, the mount point went away
+    NotFound,
+    /// ESTALE, an NFS file handle the server no longer knows
+    Stale,
+    /// ELOOP, too many symbolic links on the way to the mount point
+    Loop,
+    /// EIO, the device or the network failed
+    Io,
+    Timeout,
+    Other,
+}
+
+impl StatError {
+    pub fn kind(&self) -> StatErrorKind {
+        match self {
+            StatError::Errno(Errno::EACCES | Errno::EPERM) => StatErrorKind::PermissionDenied,
+            StatError::Errno(Errno::ENOENT) => StatErrorKind::NotFound,
+            StatError::Errno(Errno::ESTALE) => StatErrorKind::Stale,
+            StatError::Errno(Errno::ELOOP) => StatErrorKind::Loop,
+            StatError::Errno(Errno::EIO) => StatErrorKind::Io,
+            StatError::Errno(_) => StatErrorKind::Other,
+            StatError::Timeout => StatErrorKind::Timeout,
+        }
+    }
+}
+
+impl fmt::Display for StatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StatError::Errno(e) => write!(f, "{}", e.desc()),
+            StatError::Timeout => write!(f, "timed out"),
+        }
+    }
+}
+
+impl Error for StatError {}
+
+/**
+ * the message of an io error as df prints it, like StatError: "No such file or directory"
+ * without the " (os error 2)" of its Display
+ */
+pub fn io_error_desc(e: &io::Error) -> String {
+    match e.raw_os_error() {
+        Some(errno) => Errno::from_i32(errno).desc().to_string(),
+        None => e.to_string(),
+    }
+}
+
+impl From<Errno> for StatError {
+    fn from(e: Errno) -> StatError {
+        StatError::Errno(e)
+    }
+}
+
+/**
+ * the part of statvfs(3) df cares about.
+ * it comes either from the live system or from a saved copy (see parse_saved_stats)
+ */
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct FsStat {
+    pub fragment_size: u64,
+    pub blocks: u64,
+    pub blocks_free: u64,
+    pub blocks_available: u64,
+    pub files: u64,
+    pub files_free: u64,
+    pub files_available: u64,
+    pub fsid: u64,      // f_fsid, 0 when saved
+    pub flags: FsFlags, // the ST_* mount flags, none when saved
+}
+
+impl FsStat {
+    pub fn stat<P: ?Sized + NixPath>(path: &P) -> nix::Result<FsStat> {
+        statvfs(path).map(|stat| FsStat::from(&stat))
+    }
+
+    /**
+     * statvfs on a worker thread, giving up after `timeout`
+     */
+    pub fn stat_timeout(path: &Path, timeout: Duration) -> Result<FsStat, StatError> {
+        let path = path.to_path_buf();
+        with_timeout(timeout, move || FsStat::stat(&path))
+    }
+}
+
+/**
+ * run `f` on a worker thread, giving up after `timeout`.
+ * a worker stuck in the kernel is left behind, it can't be cancelled
+ */
+pub(crate) fn with_timeout<T, F>(timeout: Duration, f: F) -> Result<T, StatError>
+where
+    T: Send + 'static,
+    F: FnOnce() -> nix::Result<T> + Send + 'static,
+{
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        // nobody listens any more after a timeout
+        let _ = tx.send(f());
+    });
+    match rx.recv_timeout(timeout) {
+        Ok(result) => Ok(result?),
+        Err(_) => Err(StatError::Timeout),
+    }
+}
+
+fn syncfs(path: &Path) -> nix::Result<()> {
+    // O_NONBLOCK so that a fifo mounted over a file doesn't wait for a writer
+    let fd = open(
+        path,
+        OFlag::O_RDONLY | OFlag::O_NONBLOCK | OFlag::O_NOCTTY | OFlag::O_CLOEXEC,
+        Mode::empty(),
+    )?;
+    // SAFETY: fd is open until the close below
+    let result = Errno::result(unsafe { libc::syncfs(fd) }).map(drop);
+    let _ = close(fd);
+    result
+}
+
+/**
+ * write the cached data of the filesystem `path` is on to disk, see syncfs(2).
+ * with a `timeout` it runs on a worker like FsStat::stat_timeout
+ */
+pub fn sync_fs(path: &Path, timeout: Option<Duration>) -> Result<(), StatError> {
+    match timeout {
+        Some(timeout) => {
+            let path = path.to_path_buf();
+            with_timeout(timeout, move || syncfs(&path))
+        }
+        None => Ok(syncfs(path)?),
+    }
+}
+
+impl From<&Statvfs> for FsStat {
+    // fsblkcnt_t and friends are not 64 bits on every target
+    #[allow(clippy::unnecessary_cast)]
+    fn from(stat: &Statvfs) -> FsStat {
+        FsStat {
+            fragment_size: stat.fragment_size() as u64,
+            blocks: stat.blocks() as u64,
+            blocks_free: stat.blocks_free() as u64,
+            blocks_available: stat.blocks_available() as u64,
+            files: stat.files() as u64,
+            files_free: stat.files_free() as u64,
+            files_available: stat.files_available() as u64,
+            fsid: stat.filesystem_id() as u64,
+            flags: stat.flags(),
+        }
+    }
+}
+
+/**
+ * parse saved statvfs data, one mount per line:
+ * FRSIZE BLOCKS BFREE BAVAIL FILES FFREE FAVAIL MOUNTPOINT
+ *
+ * the mount point is the rest of the line and may use the mountinfo octal escapes.
+ * `stat -f -c '%S %b %f %a %c %d %d %n' MOUNTPOINT...` writes this format.
+ * lines that can not be parsed are ignored.
+ */
+pub fn parse_saved_stats(content: &[u8]) -> HashMap<PathBuf, FsStat> {
+    let mut stats = HashMap::new();
+    for line in content.split(|&b| b == b'\n') {
+        let mut numbers: Vec<u64> = vec![];
+        let mut rest = line;
+        while numbers.len() < 7 {
+            let trimmed = match rest.iter().position(|b| !b.is_ascii_whitespace()) {
+                Some(start) => &rest[start..],
+                None => break,
+            };
+            let end = trimmed
+                .iter()
+                .position(u8::is_ascii_whitespace)
+                .unwrap_or(trimmed.len());
+            match std::str::from_utf8(&trimmed[..end])
+                .ok()
+                .and_then(|x| x.parse().ok())
+            {
+                Some(n) => numbers.push(n),
+                None => break,
+            }
+            rest = &trimmed[end..];
+        }
+        // exactly one separator before the mount point
+        if numbers.len() != 7 || rest.len() < 2 {
+            continue;
+        }
+        stats.insert(
+            PathBuf::from(unescape(&rest[1..])),
+            FsStat {
+                fragment_size: numbers[0],
+                blocks: numbers[1],
+                blocks_free: numbers[2],
+                blocks_available: numbers[3],
+                files: numbers[4],
+                files_free: numbers[5],
+                files_available: numbers[6],
+                ..Default::default()
+            },
+        );
+    }
+    stats
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fsstat::{
+        io_error_desc, parse_saved_stats, sync_fs, FsStat, StatError, StatErrorKind,
+    };
+    use nix::errno::Errno;
+    use std::io;
+    use std::path::Path;
+    use std::time::Duration;
+
+    #[test]
+    fn test_sync_fs() {
+        let dir = std::env::temp_dir();
+        assert_eq!(sync_fs(&dir, None), Ok(()));
+        assert_eq!(sync_fs(&dir, Some(Duration::from_secs(60))), Ok(()));
+        assert_eq!(
+            sync_fs(Path::new("/no/such/dir"), None),
+            Err(StatError::Errno(Errno::ENOENT))
+        );
+    }
+
+    #[test]
+    fn test_stat_timeout() {
+        let stat = FsStat::stat_timeout(Path::new("/"), Duration::from_secs(60)).unwrap();
+        assert!(stat.fragment_size > 0);
+        assert_eq!(
+            FsStat::stat_timeout(Path::new("/no/such/dir"), Duration::from_secs(60)),
+            Err(StatError::Errno(Errno::ENOENT))
+        );
+        assert_eq!(StatError::Timeout.to_string(), "timed out");
+
+        let kind = |errno| StatError::Errno(errno).kind();
+        assert_eq!(kind(Errno::EACCES), StatErrorKind::PermissionDenied);
+        assert_eq!(kind(Errno::ESTALE), StatErrorKind::Stale);
+        assert_eq!(kind(Errno::ENOTCONN), StatErrorKind::Other);
+        assert_eq!(StatError::Errno(Errno::ESTALE).to_string(), "Stale file handle");
+    }
+
+    #[test]
+    fn test_io_error_desc() {
+        let e = io::Error::from_raw_os_error(Errno::ENOENT as i32);
+        assert_eq!(io_error_desc(&e), "No such file or directory");
+        let e = io::Error::new(io::ErrorKind::InvalidData, "bad table");
+        assert_eq!(io_error_desc(&e), "bad table");
+    }
+
+    #[test]
+    fn test_parse_saved_stats() {
+        let content = b"\
+4096 61202533 20454286 17322850 15597568 14689164 14689164 /
+4096 2034944 2034944 2034944 2034944 2034943 2034943 /mnt/My Disk
+512 10 5 5 0 0 0 /mnt/tab\\011name
+garbage line
+4096 1 2 3 /short
+";
+        let stats = parse_saved_stats(content);
+        assert_eq!(stats.len(), 3);
+        let root = stats[Path::new("/")];
+        assert_eq!(root.fragment_size, 4096);
+        assert_eq!(root.blocks, 61202533);
+        assert_eq!(root.blocks_available, 17322850);
+        assert_eq!(root.files_available, 14689164);
+        assert!(stats.contains_key(Path::new("/mnt/My Disk")));
+        assert!(stats.contains_key(Path::new("/mnt/tab\tname")));
+    }
+}
@@ -0,0 +1,91 @@
+use std::fmt;
+
+/**
+ * just enough JSON for the machine readable output
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Number(i128),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    fn escape(s: &str) -> String {
+        let mut escaped = String::with_capacity(s.len() + 2);
+        escaped.push('"');
+        for c in s.chars() {
+            match c {
+                '"' => escaped.push_str("\\\""),
+                '\\' => escaped.push_str("\\\\"),
+                '\n' => escaped.push_str("\\n"),
+                '\t' => escaped.push_str("\\t"),
+                c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+                c => escaped.push(c),
+            }
+        }
+        escaped.push('"');
+        escaped
+    }
+
+    /**
+     * pretty print with two spaces per level
+     */
+    fn write(&self, f: &mut fmt::Formatter<'_>, indent: usize) -> fmt::Result {
+        let pad = |n: usize| "  ".repeat(n);
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Number(n) => write!(f, "{}", n),
+            Json::String(s) => write!(f, "{}", Self::escape(s)),
+            Json::Array(items) if items.is_empty() => write!(f, "[]"),
+            Json::Array(items) => {
+                writeln!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    write!(f, "{}", pad(indent + 1))?;
+                    item.write(f, indent + 1)?;
+                    writeln!(f, "{}", if i + 1 < items.len() { "," } else { "" })?;
+                }
+                write!(f, "{}]", pad(indent))
+            }
+            Json::Object(members) if members.is_empty() => write!(f, "{{}}"),
+            Json::Object(members) => {
+                writeln!(f, "{{")?;
+                for (i, (key, value)) in members.iter().enumerate() {
+                    write!(f, "{}{}: ", pad(indent + 1), Self::escape(key))?;
+                    value.write(f, indent + 1)?;
+                    writeln!(f, "{}", if i + 1 < members.len() { "," } else { "" })?;
+                }
+                write!(f, "{}}}", pad(indent))
+            }
+        }
+    }
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write(f, 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::json::Json;
+
+    #[test]
+    fn test_json() {
+        let json = Json::Object(vec![
+            ("source".to_string(), Json::String("/dev/sda1".to_string())),
+            ("size".to_string(), Json::Number(-1)),
+            ("file".to_string(), Json::Null),
+            ("children".to_string(), Json::Array(vec![])),
+        ]);
+        assert_eq!(
+            json.to_string(),
+            "{\n  \"source\": \"/dev/sda1\",\n  \"size\": -1,\n  \"file\": null,\n  \"children\": []\n}"
+        );
+        let json = Json::Array(vec![Json::String("a \"b\"\\\n\u{1}".to_string())]);
+        assert_eq!(json.to_string(), "[\n  \"a \\\"b\\\"\\\\\\n\\u0001\"\n]");
+    }
+}
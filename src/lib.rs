@@ -0,0 +1,76 @@
+//! df as a library: read the mount table, pick the filesystems df would show
+//! and get their usage.
+//!
+//! ```no_run
+//! let filter = df::Filter::new().local(true).exclude_type("tmpfs");
+//! let mut warnings = vec![];
+//! for fs in df::filesystems(&filter, &mut warnings).unwrap() {
+//!     println!("{} {}K used, {}K free", fs.target, fs.used / 1024, fs.avail / 1024);
+//! }
+//! for warning in warnings {
+//!     eprintln!("{}", warning);
+//! }
+//! ```
+
+pub mod filter;
+pub mod fsclass;
+pub mod fsinfo;
+pub mod fsstat;
+pub mod locate;
+pub mod mountinfo;
+pub mod mountopts;
+pub mod report;
+pub mod sysroot;
+pub mod table;
+pub mod tree;
+pub mod usage;
+pub mod watch;
+
+pub use filter::Filter;
+pub use mountinfo::{MountInfo, MountInfoError};
+pub use report::{Message, Query, Report};
+pub use usage::FsUsage;
+
+use fsstat::StatError;
+use mountinfo::{MountSource, ParseMode};
+use std::time::Duration;
+use sysroot::Sysroot;
+
+/**
+ * the mounts of this process, from /proc/self/mountinfo
+ * (/proc/mounts or /etc/mtab when there is no mountinfo).
+ * lines that can not be parsed are skipped and told about in `warnings`
+ */
+pub fn mounts(warnings: &mut Vec<String>) -> Result<Vec<MountInfo>, MountInfoError> {
+    mountinfo::get_mountinfo_list(&MountSource::default(), ParseMode::Lenient, warnings)
+}
+
+/**
+ * statvfs the filesystem mounted on `mount`
+ */
+pub fn usage(mount: &MountInfo) -> Result<FsUsage, StatError> {
+    Sysroot::default().usage(mount, None)
+}
+
+/**
+ * statvfs the filesystem mounted on `mount`, giving up after `timeout`,
+ * e.g. on a hung NFS server
+ */
+pub fn usage_timeout(mount: &MountInfo, timeout: Duration) -> Result<FsUsage, StatError> {
+    Sysroot::default().usage(mount, Some(timeout))
+}
+
+/**
+ * the rows df would show for `filter`: the mounts it lets through, without
+ * duplicates, and their usage, see Query::run.
+ * what df would print on stderr, like skipped mount table lines, bad config files
+ * and mounts that can not be stat'ed, is told about in `warnings`
+ */
+pub fn filesystems(
+    filter: &Filter,
+    warnings: &mut Vec<String>,
+) -> Result<Vec<FsUsage>, MountInfoError> {
+    let report = Query::new(filter.clone()).run()?;
+    warnings.extend(report.messages.iter().map(Message::to_string));
+    Ok(report.rows.into_iter().map(|row| row.usage).collect())
+}
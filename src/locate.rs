@@ -0,0 +1,264 @@
+use crate::fsstat::io_error_desc;
+use crate::mountinfo::MountInfo;
+use crate::sysroot::Sysroot;
+use nix::libc;
+use std::error::Error;
+use std::ffi::CString;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::mem::MaybeUninit;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
+use std::path::{Component, Path, PathBuf};
+
+#[derive(Debug)]
+pub enum LocateError {
+    /// the file can not be looked up
+    Io(io::Error),
+    /// no mount point is a prefix of the path
+    NoMountPoint,
+    /// a block device that is not mounted
+    NotMounted,
+}
+
+impl fmt::Display for LocateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LocateError::Io(e) => write!(f, "{}", io_error_desc(e)),
+            LocateError::NoMountPoint => write!(f, "cannot find mount point"),
+            LocateError::NotMounted => write!(f, "device is not mounted"),
+        }
+    }
+}
+
+impl Error for LocateError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            LocateError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for LocateError {
+    fn from(e: io::Error) -> LocateError {
+        LocateError::Io(e)
+    }
+}
+
+/**
+ * ID of the mount `path` is on, from statx(2) STATX_MNT_ID (linux 5.8 and later).
+ * it is the mount ID of mountinfo
+ */
+pub fn statx_mount_id(path: &Path) -> Option<u32> {
+    let cpath = CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut buf = MaybeUninit::<libc::statx>::zeroed();
+    // SAFETY: cpath is a valid C string and buf is big enough for a struct statx
+    let ret = unsafe {
+        libc::statx(
+            libc::AT_FDCWD,
+            cpath.as_ptr(),
+            0,
+            libc::STATX_MNT_ID,
+            buf.as_mut_ptr(),
+        )
+    };
+    if ret != 0 {
+        return None;
+    }
+    // SAFETY: statx succeeded and filled buf
+    let buf = unsafe { buf.assume_init() };
+    if buf.stx_mask & libc::STATX_MNT_ID == 0 {
+        return None;
+    }
+    Some(buf.stx_mnt_id as u32)
+}
+
+/**
+ * remove "." and "..", without looking at the filesystem
+ */
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::from("/");
+    for component in path.components() {
+        match component {
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            Component::Normal(name) => normalized.push(name),
+            _ => {}
+        }
+    }
+    normalized
+}
+
+/**
+ * the mount with the longest mount point that `path` is under.
+ * when `dev` is given, mounts of that device come first,
+ * the last one wins when a mount point is mounted over
+ */
+pub fn longest_prefix<'a>(
+    list: &'a [MountInfo],
+    path: &Path,
+    dev: Option<u64>,
+) -> Option<&'a MountInfo> {
+    let under = |mount: &&MountInfo| path.starts_with(&mount.mount_point);
+    let longest = |a: &&MountInfo, b: &&MountInfo| {
+        a.mount_point
+            .as_os_str()
+            .len()
+            .cmp(&b.mount_point.as_os_str().len())
+    };
+    dev.and_then(|dev| {
+        list.iter()
+            .filter(under)
+            .filter(|mount| mount.dev() == dev)
+            .max_by(longest)
+    })
+    .or_else(|| list.iter().filter(under).max_by(longest))
+}
+
+/**
+ * where block device `device` (given by name, or also by device number) is mounted.
+ * a mount of the whole filesystem is preferred over bind mounts of a part of it,
+ * then the shortest mount point
+ */
+pub fn device_mount<'a>(
+    list: &'a [MountInfo],
+    device: &Path,
+    rdev: Option<u64>,
+) -> Option<&'a MountInfo> {
+    list.iter()
+        .filter(|mount| {
+            mount.mount_source == device.as_os_str()
+                || (mount.dev_known() && Some(mount.dev()) == rdev)
+        })
+        .min_by_key(|mount| {
+            (
+                mount.root != Path::new("/"),
+                mount.mount_point.as_os_str().len(),
+            )
+        })
+}
+
+/**
+ * the mount in `list` that `file` is on, like df FILE.
+ * statx tells the mount on the live system, the longest mount point that is
+ * a prefix of the resolved path is used when it can't.
+ * for a block device it is where the device is mounted, not the /dev it is in.
+ * the paths of an offline host are only compared, never looked up
+ */
+pub fn locate<'a>(
+    sysroot: &Sysroot,
+    list: &'a [MountInfo],
+    file: &Path,
+) -> Result<&'a MountInfo, LocateError> {
+    if !sysroot.is_live() {
+        if let Some(mount) = device_mount(list, file, None) {
+            return Ok(mount);
+        }
+        return longest_prefix(list, &normalize(file), None).ok_or(LocateError::NoMountPoint);
+    }
+    let path = match sysroot.pid() {
+        // the file is in the mount namespace of the process, seen through its root
+        Some(_) if file.is_absolute() => sysroot.stat_path(file),
+        _ => file.to_path_buf(),
+    };
+    let meta = fs::metadata(&path)?;
+    if meta.file_type().is_block_device() {
+        return device_mount(list, file, Some(meta.rdev())).ok_or(LocateError::NotMounted);
+    }
+    if let Some(id) = statx_mount_id(&path) {
+        if let Some(mount) = list.iter().find(|mount| mount.mount_id == id) {
+            return Ok(mount);
+        }
+    }
+    let resolved = match sysroot.pid() {
+        Some(_) => normalize(file),
+        None => fs::canonicalize(&path)?,
+    };
+    longest_prefix(list, &resolved, Some(meta.dev())).ok_or(LocateError::NoMountPoint)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::locate::{
+        device_mount, locate, longest_prefix, normalize, statx_mount_id, LocateError,
+    };
+    use crate::mountinfo::{table, MountSource, ParseMode};
+    use crate::sysroot::Sysroot;
+    use std::path::Path;
+
+    #[test]
+    fn test_longest_prefix() {
+        let list = table(&[
+            "22 1 8:1 / / rw - ext4 /dev/sda1 rw",
+            "23 22 8:2 / /home rw - ext4 /dev/sda2 rw",
+            "24 23 8:3 / /home/user/data rw - xfs /dev/sda3 rw",
+            "25 22 0:40 / /homework rw - tmpfs tmpfs rw",
+            "26 23 0:41 / /home rw - tmpfs tmpfs rw",
+        ]);
+        let id = |path: &str, dev: Option<u64>| {
+            longest_prefix(&list, Path::new(path), dev).map(|x| x.mount_id)
+        };
+        assert_eq!(id("/etc/passwd", None), Some(22));
+        assert_eq!(id("/home/user/data/x", None), Some(24));
+        assert_eq!(id("/homework/a", None), Some(25));
+        // /home mounted over, the later mount wins
+        assert_eq!(id("/home/user", None), Some(26));
+        assert_eq!(id("/home/user", Some(list[1].dev())), Some(23));
+        assert_eq!(id("relative", None), None);
+    }
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize(Path::new("/a/./b/../c/")), Path::new("/a/c"));
+        assert_eq!(normalize(Path::new("/../..")), Path::new("/"));
+    }
+
+    #[test]
+    fn test_locate() {
+        let sysroot = Sysroot::default();
+        let list = MountSource::default().read(ParseMode::Strict, &mut vec![]).unwrap();
+        let here = env!("CARGO_MANIFEST_DIR");
+        let mount = locate(&sysroot, &list, Path::new(here)).unwrap();
+        if let Some(id) = statx_mount_id(Path::new(here)) {
+            assert_eq!(mount.mount_id, id);
+        }
+        assert!(Path::new(here).starts_with(&mount.mount_point));
+        assert!(matches!(
+            locate(&sysroot, &list, Path::new("/no/such/file")),
+            Err(LocateError::Io(_))
+        ));
+
+        let fixture = Path::new(here).join("tests/fixtures/mountinfo");
+        let list = MountSource::File(fixture).read(ParseMode::Strict, &mut vec![]).unwrap();
+        let offline = Sysroot::new(Some(Path::new("/nonexistent")), None);
+        let mount = locate(&offline, &list, Path::new("/boot/../boot/efi/EFI")).unwrap();
+        assert_eq!(mount.mount_id, 40);
+        let mount = locate(&offline, &list, Path::new("/dev/nvme0n1p1")).unwrap();
+        assert_eq!(mount.mount_point, Path::new("/boot/efi"));
+        assert!(matches!(
+            locate(&offline, &list[..1], Path::new("/boot")),
+            Err(LocateError::NoMountPoint)
+        ));
+    }
+
+    #[test]
+    fn test_device_mount() {
+        let list = table(&[
+            "22 1 8:1 / / rw - ext4 /dev/sda1 rw",
+            "30 22 8:2 /data /srv rw - ext4 /dev/sda2 rw",
+            "31 22 8:2 / /mnt/disk rw - ext4 /dev/sda2 rw",
+            "32 22 8:2 / /d rw - ext4 /dev/sda2 rw",
+            "33 22 0:5 / /dev rw - devtmpfs udev rw",
+        ]);
+        let dev = |major, minor| Some(nix::sys::stat::makedev(major, minor));
+        let id =
+            |device: &str, rdev| device_mount(&list, Path::new(device), rdev).map(|x| x.mount_id);
+        // the whole filesystem, then the shortest mount point
+        assert_eq!(id("/dev/disk/by-label/data", dev(8, 2)), Some(32));
+        assert_eq!(id("/dev/sda1", None), Some(22));
+        assert_eq!(id("/dev/sdb1", dev(8, 17)), None);
+    }
+}
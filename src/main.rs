@@ -1,183 +1,85 @@
+mod blocksize;
 mod cli;
-mod mountinfo;
-mod table;
+mod json;
 
 use crate::cli::parse_args;
-use crate::table::FieldAlign::{Left, Right};
 use cli::Options;
-use mountinfo::MountInfo;
-use nix;
-use nix::sys::statvfs::Statvfs;
-use std::collections::{HashMap, HashSet};
-use std::fs::read_to_string;
-use std::process::id;
-use table::{FieldAlign, Table};
-
-#[derive(Debug, Default)]
-struct FsUsage {
-    source: String,
-    fstype: String,
-    file: String,
-    target: String,
-    itotal: u64,
-    iused: u64,
-    iavail: u64,
-    ipcent: u32,
-    size: u64,
-    used: u64,
-    avail: u64,
-    pcent: u32,
-}
+use df::fsclass::FsClassifier;
+use df::fsinfo::FsInfo;
+use df::mountinfo::MountInfoError;
+use df::sysroot::Sysroot;
+use df::watch::{self, timestamp};
+use df::table::FieldAlign::{Left, Right};
+use df::table::Table;
+use df::{tree, FsUsage, Query};
+use json::Json;
+use std::io::{self, Write};
+use std::process;
+use std::time::SystemTime;
 
-impl FsUsage {
-    fn new() -> FsUsage {
-        FsUsage {
-            source: "-".to_owned(),
-            fstype: "-".to_owned(),
-            file: "-".to_owned(),
-            target: "-".to_owned(),
-            ..Default::default()
-        }
-    }
-}
+/**
+ * (field name, label) of every output field
+ */
+const FIELDS: [(&str, &str); 24] = [
+    ("source", "Filesystem"),
+    ("fstype", "Type"),
+    ("file", "File"),
+    ("target", "Mounted on"),
+    ("itotal", "Inodes"),
+    ("iused", "IUsed"),
+    ("iavail", "IFree"),
+    ("ipcent", "IUse%"),
+    ("size", "1K-blocks"),
+    ("used", "Used"),
+    ("avail", "Avail"),
+    ("pcent", "Use%"),
+    ("maj:min", "MAJ:MIN"),
+    ("propagation", "Propagation"),
+    ("opts", "Options"),
+    ("sopts", "Super options"),
+    ("magic", "Magic"),
+    ("fsid", "FSID"),
+    ("bsize", "Bsize"),
+    ("frsize", "Frsize"),
+    ("namemax", "Namemax"),
+    ("flags", "Flags"),
+    ("reserved", "Reserved"),
+    ("rpcent", "Res%"),
+];
 
 /**
- * a/b = ?%
+ * labels of the fields that need a statfs of the mount
  */
-fn percent_round_up(a: u64, b: u64) -> u32 {
-    if 100u64 * a % b == 0 {
-        (100u64 * a / b) as u32
-    } else {
-        1 + (100u64 * a / b) as u32
-    }
-}
+const STATFS_FIELDS: [&str; 6] = ["Magic", "FSID", "Bsize", "Frsize", "Namemax", "Flags"];
 
 fn fieldname_to_label(s: &str) -> &str {
-    match s {
-        "source" => "Filesystem",
-        "fstype" => "Type",
-        "file" => "File",
-        "target" => "Mounted on",
-        "itotal" => "Inodes",
-        "iused" => "IUsed",
-        "iavail" => "IFree",
-        "ipcent" => "IUse%",
-        "size" => "1K-blocks",
-        "used" => "Used",
-        "avail" => "Avail",
-        "pcent" => "Use%",
-        _ => "",
-    }
+    FIELDS
+        .iter()
+        .find(|(name, _)| *name == s)
+        .map_or("", |(_, label)| label)
 }
 
-// power should be 1000 or 1024
-fn human_readable(size: u64, power: u64) -> String {
-    let surfix = ["B", "K", "M", "G", "T", "P"];
-
-    // short cut
-    if size == 0 {
-        return "0".to_string();
-    }
-    if size < power {
-        return format!("{}B", size);
-    }
-
-    // left.right<Unit>
-    // 1.9G etc.
-    let mut left = 0;
-    let mut right = 0;
-
-    // left
-    let mut i: u32 = 0;
-    while size >= power.pow(i) {
-        i += 1;
+fn label_to_fieldname(s: &str) -> &str {
+    // the size label changes with -h/-H and -B
+    if s == "Size" || s.ends_with("-blocks") {
+        return "size";
     }
-    i -= 1;
-
-    let weight = power.pow(i);
-    left = size / weight;
-
-    // right
-    let fraction = ((size % weight) as f64 / weight as f64) * 10f64;
-    right = fraction.ceil() as u64;
-    if right >= 10 {
-        left += 1;
-        right = 0;
-    }
-
-    if left >= 10 {
-        // round up right
-        if right != 0 {
-            left += 1;
-        }
-        return format!("{}{}", left, surfix[i as usize]);
-    }
-
-    format!("{}.{}{}", left, right, surfix[i as usize])
+    FIELDS
+        .iter()
+        .find(|(_, label)| *label == s)
+        .map_or("", |(name, _)| name)
 }
 
-fn get_dev(mount: MountInfo, options: &Options) -> Option<FsUsage> {
-    if mount.is_remote() && options.show_local_fs {
-        return None;
-    }
-    if mount.is_dummy() && !options.show_all_fs && options.listed_fs.is_empty() {
-        return None;
-    }
-    // fs_type not listed
-    if !options.listed_fs.is_empty() && !options.listed_fs.contains(&mount.fs_type)
-        || options.excluded_fs.contains(&mount.fs_type)
-    {
-        return None;
-    }
-
-    // TODO
-    // grand total
-    let mut fs_usage = FsUsage::new();
-
-    // stat the fs
-    if let Ok(stat) = nix::sys::statvfs::statvfs::<str>(mount.mount_point.as_ref()) {
-        // 信息读取成功
-        // 填充各种信息
-        // block
-        fs_usage.size = stat.blocks();
-        fs_usage.used = stat.blocks() - stat.blocks_free();
-        fs_usage.avail = stat.blocks_available();
-        // 显示时按照1K大小的块显示
-        fs_usage.size = fs_usage.size * stat.fragment_size() / 1024;
-        fs_usage.used = fs_usage.used * stat.fragment_size() / 1024;
-        fs_usage.avail = fs_usage.avail * stat.fragment_size() / 1024;
-        // round up
-        fs_usage.pcent = match fs_usage.size != 0 {
-            true => percent_round_up(fs_usage.used, fs_usage.used + fs_usage.avail), // 不是除fs_usage.size，见coreutils中的df
-            false => 0,
-        };
-        // inode
-        fs_usage.itotal = stat.files();
-        fs_usage.iused = stat.files() - stat.files_available();
-        fs_usage.iavail = stat.files_available();
-        fs_usage.ipcent = match fs_usage.itotal != 0 {
-            true => percent_round_up(fs_usage.iused, fs_usage.iused + fs_usage.iavail),
-            false => 0,
-        };
-
-        fs_usage.fstype = mount.fs_type;
-        fs_usage.source = mount.mount_source;
-        fs_usage.target = mount.mount_point;
-    } else {
-        // TODO 判断是不是权限原因
-        return None;
-    }
-
-    if fs_usage.size == 0 && !options.show_all_fs && options.listed_fs.is_empty() {
-        return None;
-    }
-
-    Some(fs_usage)
+/**
+ * a Use% column, "-" when there is nothing to divide by or it is negative, see FsUsage
+ */
+fn percent(pcent: Option<i32>) -> String {
+    pcent.map_or_else(|| "-".to_string(), |x| format!("{}%", x))
 }
 
 fn options_to_fields(options: &Options) -> Vec<String> {
     if options.output_all_fields {
-        return vec![
+        return [
             "Filesystem",
             "Type",
             "Inodes",
@@ -208,7 +110,7 @@ fn options_to_fields(options: &Options) -> Vec<String> {
     }
     // 没有写明fields, 默认现实block，写明了inode则显示inodes
     if options.inodes {
-        return vec![
+        return [
             "Filesystem",
             "Inodes",
             "IUsed",
@@ -220,7 +122,21 @@ fn options_to_fields(options: &Options) -> Vec<String> {
         .map(|x| x.to_string())
         .collect();
     }
-    vec![
+    // -P, the order POSIX specifies
+    if options.portability {
+        return [
+            "Filesystem",
+            "1K-blocks",
+            "Used",
+            "Avail",
+            "Use%",
+            "Mounted on",
+        ]
+        .iter()
+        .map(|x| x.to_string())
+        .collect();
+    }
+    [
         "Filesystem",
         "1K-blocks",
         "Used",
@@ -234,20 +150,214 @@ fn options_to_fields(options: &Options) -> Vec<String> {
     .collect()
 }
 
-fn show_table(options: &Options, table: &Table) {
+/**
+ * (label in FIELDS, label shown) of the fields whose header depends on the options
+ */
+fn renamed_fields(options: &Options) -> Vec<(&'static str, String)> {
+    match options.portability {
+        true => vec![
+            ("1K-blocks", options.block_size.posix_header()),
+            ("Avail", "Available".to_string()),
+            ("Use%", "Capacity".to_string()),
+        ],
+        false => vec![("1K-blocks", options.block_size.header())],
+    }
+}
+
+/**
+ * labels of the fields to show, as they are named in the table
+ */
+fn displayed_fields(options: &Options) -> Vec<String> {
     let mut fields = options_to_fields(options);
-    if options.human_readable {
-        for i in 0..fields.len() {
-            if fields[i].eq("1K-blocks") {
-                fields[i] = "Size".to_owned();
-            }
+    let renamed = renamed_fields(options);
+    for field in fields.iter_mut() {
+        if let Some((_, label)) = renamed.iter().find(|(orig, _)| orig == field) {
+            *field = label.clone();
+        }
+    }
+    fields
+}
+
+fn show_table(options: &Options, table: &Table, depths: &[usize]) {
+    let fields = displayed_fields(options);
+    if options.tree {
+        // indent the mount points under their parents
+        let mut table = table.clone();
+        for (row, prefix) in tree::tree_prefixes(depths).iter().enumerate() {
+            let target = format!("{}{}", prefix, table.get(row, "Mounted on").unwrap_or(""));
+            table.set(row, "Mounted on", &target);
         }
+        println!("{}", table.to_string_partial(&fields));
+        return;
     }
     println!("{}", table.to_string_partial(&fields));
 }
 
-fn get_all_entries(options: &Options) -> Table {
-    let mountlist = filter_mountinfo_list(mountinfo::get_mountinfo_list(), options);
+fn row_to_json(table: &Table, fields: &[String], row: usize) -> Vec<(String, Json)> {
+    let numeric = [
+        "itotal", "iused", "iavail", "size", "used", "avail", "bsize", "frsize", "namemax",
+        "reserved",
+    ];
+    let mut object = vec![];
+    for field in fields.iter() {
+        let name = label_to_fieldname(field);
+        let value = table.get(row, field).unwrap_or("-");
+        let value = match value.parse::<i128>() {
+            _ if value == "-" => Json::Null,
+            Ok(n) if numeric.contains(&name) => Json::Number(n),
+            _ => Json::String(value.to_string()),
+        };
+        object.push((name.to_string(), value));
+    }
+    object
+}
+
+/**
+ * rows from `*pos` on at `depth`, each with its deeper rows as children
+ */
+fn json_children(
+    table: &Table,
+    fields: &[String],
+    depths: &[usize],
+    pos: &mut usize,
+    depth: usize,
+) -> Vec<Json> {
+    let mut nodes = vec![];
+    while *pos < depths.len() && depths[*pos] == depth {
+        let mut object = row_to_json(table, fields, *pos);
+        *pos += 1;
+        let children = json_children(table, fields, depths, pos, depth + 1);
+        if !children.is_empty() {
+            object.push(("children".to_string(), Json::Array(children)));
+        }
+        nodes.push(Json::Object(object));
+    }
+    nodes
+}
+
+fn show_json(options: &Options, table: &Table, depths: &[usize]) {
+    let fields = displayed_fields(options);
+    let filesystems = json_children(table, &fields, depths, &mut 0, 0);
+    let mut object = vec![("filesystems".to_string(), Json::Array(filesystems))];
+    // the --total row is the one after the filesystems
+    if table.len() > depths.len() {
+        let total = row_to_json(table, &fields, depths.len());
+        object.push(("total".to_string(), Json::Object(total)));
+    }
+    println!("{}", Json::Object(object));
+}
+
+/**
+ * the cells of `fsu` in the order of the table's fields
+ */
+fn usage_to_row(fsu: FsUsage, info: Option<FsInfo>, options: &Options) -> Vec<String> {
+    let mut row: Vec<String> = Vec::with_capacity(FIELDS.len());
+
+    // source
+    row.push(fsu.source);
+
+    // fs_type
+    row.push(fsu.fstype);
+
+    // Inodes
+    row.push(fsu.itotal.to_string());
+
+    // Inode used
+    row.push(fsu.iused.to_string());
+
+    // Inode free
+    row.push(fsu.iavail.to_string());
+
+    // Inode percent
+    row.push(percent(fsu.ipcent));
+
+    // blocks
+    row.push(options.block_size.show(fsu.size));
+
+    // block used
+    row.push(options.block_size.show(fsu.used));
+
+    // block available
+    row.push(options.block_size.show(fsu.avail));
+
+    // block used percent
+    row.push(percent(fsu.pcent));
+
+    // the FILE argument on this mount
+    row.push(fsu.file);
+
+    // mount point
+    row.push(fsu.target.to_string());
+
+    // device number
+    row.push(fsu.majmin);
+
+    // propagation
+    row.push(fsu.propagation);
+
+    // per-mount and per-superblock options
+    row.push(fsu.opts);
+    row.push(fsu.sopts);
+
+    // from statfs
+    match info {
+        Some(info) => {
+            row.push(info.magic_name());
+            row.push(format!("{:x}", info.fsid));
+            row.push(info.block_size.to_string());
+            row.push(info.fragment_size.to_string());
+            row.push(info.name_max.to_string());
+            row.push(info.flag_names());
+        }
+        None => row.extend(vec!["-".to_string(); 6]),
+    }
+
+    // space only root can use
+    if !fsu.usage_known {
+        row.extend(vec!["-".to_string(); 2]);
+    } else {
+        row.push(options.block_size.show(fsu.reserved));
+        row.push(percent(fsu.rpcent));
+    }
+
+    // usage unknown, e.g. not saved for an offline host
+    if !fsu.usage_known {
+        for x in row[2..10].iter_mut() {
+            *x = "-".to_string();
+        }
+    }
+    if fsu.timed_out {
+        row[5] = "timeout".to_string();
+        row[9] = "timeout".to_string();
+    }
+    row
+}
+
+/**
+ * returns the table, the tree depth of each row and whether a FILE argument or
+ * a file system could not be read.
+ * rows are in tree order with --tree, the --total row comes last and has no depth
+ */
+fn get_all_entries(options: &Options) -> Result<(Table, Vec<usize>, bool), MountInfoError> {
+    let statfs = displayed_fields(options)
+        .iter()
+        .any(|x| STATFS_FIELDS.contains(&x.as_str()));
+    let mut query = Query::new(options.filter())
+        .mount_table(options.mount_table.as_deref())
+        .sysroot(options.sysroot.as_deref())
+        .pid(options.pid)
+        .timeout(options.timeout)
+        .jobs(options.jobs)
+        .sync(options.sync)
+        .as_root(options.as_root)
+        .statfs(statfs);
+    for file in options.files.iter() {
+        query = query.file(file);
+    }
+    let report = query.run()?;
+    for message in report.messages.iter() {
+        eprintln!("df: {}", message);
+    }
 
     let fields = vec![
         "Filesystem",
@@ -262,152 +372,198 @@ fn get_all_entries(options: &Options) -> Table {
         "Use%",
         "File",
         "Mounted on",
+        "MAJ:MIN",
+        "Propagation",
+        "Options",
+        "Super options",
+        "Magic",
+        "FSID",
+        "Bsize",
+        "Frsize",
+        "Namemax",
+        "Flags",
+        "Reserved",
+        "Res%",
     ];
     let align_list = vec![
-        Left, Left, Right, Right, Right, Right, Right, Right, Right, Right, Left, Left,
+        Left, Left, Right, Right, Right, Right, Right, Right, Right, Right, Left, Left, Left,
+        Left, Left, Left, Left, Left, Right, Right, Right, Left, Right, Right,
     ];
     let mut table = Table::new(&fields);
     for (i, align) in align_list.into_iter().enumerate() {
         table.set_field_align(fields[i], align);
     }
 
-    // get fs usage
-    for mount in mountlist.into_iter() {
-        if let Some(fsu) = get_dev(mount, options) {
-            // populate a table
-            let mut row: Vec<String> = vec![];
-
-            // source
-            row.push(fsu.source);
-
-            // fs_type
-            row.push(fsu.fstype);
-
-            // Inodes
-            row.push(fsu.itotal.to_string());
-
-            // Inode used
-            row.push(fsu.iused.to_string());
-
-            // Inode free
-            row.push(fsu.iavail.to_string());
-
-            // Inode percent
-            if fsu.itotal == 0 {
-                row.push("-".to_string());
-            } else {
-                row.push(fsu.ipcent.to_string() + "%");
-            }
-
-            if options.human_readable {
-                let power: u64 = match options.human_readable_1024 {
-                    true => 1024,
-                    false => 1000,
-                };
-                // blocks
-                row.push(human_readable(fsu.size*1024, power));
-
-                // block used
-                row.push(human_readable(fsu.used*1024, power));
-
-                // block available
-                row.push(human_readable(fsu.avail*1024, power));
-            } else {
-                // blocks
-                row.push(fsu.size.to_string());
-
-                // block used
-                row.push(fsu.used.to_string());
-
-                // block available
-                row.push(fsu.avail.to_string());
-            }
-
-            // block used percent
-            row.push(fsu.pcent.to_string() + "%");
-
-            // TODO File
-            row.push("-".to_string());
+    let mut rows: Vec<Vec<String>> = vec![];
+    let mut ids: Vec<u32> = vec![];
+    let mut shown: Vec<FsUsage> = vec![];
+    for row in report.rows {
+        if options.print_grand_total {
+            shown.push(row.usage.clone());
+        }
+        rows.push(usage_to_row(row.usage, row.info, options));
+        ids.push(row.mount_id);
+    }
 
-            // mount point
-            row.push(fsu.target.to_string());
+    let order: Vec<(usize, usize)> = match options.tree {
+        true => tree::tree_order(&ids, &report.parents),
+        false => (0..rows.len()).map(|i| (i, 0)).collect(),
+    };
+    let mut depths = vec![];
+    for (i, depth) in order.into_iter() {
+        table.add_row(&rows[i]);
+        depths.push(depth);
+    }
 
-            table.add_row(&row);
+    // --total, the last row, outside of the tree
+    if options.print_grand_total && !rows.is_empty() {
+        table.add_row(&usage_to_row(FsUsage::total(&shown), None, options));
+        // where the label goes when there is no Filesystem column
+        if !options_to_fields(options).iter().any(|x| x == "Filesystem") {
+            table.set(table.len() - 1, "Mounted on", "total");
         }
     }
 
-    if options.human_readable {
-        table.change_field_name("1K-blocks", "Size");
+    for (orig, label) in renamed_fields(options) {
+        if orig != label {
+            table.change_field_name(orig, &label);
+        }
     }
 
-    table
+    Ok((table, depths, report.failed))
 }
 
 /**
- * df.c中的filter_mountinfo_list的作用是去重，而不是根据输入的参数过滤掉mountinfo
- * 真正的过滤在get_dev中
+ * print the changes to the mount table as they happen, see watch::watch_mounts
  */
-fn filter_mountinfo_list(list: Vec<MountInfo>, options: &Options) -> Vec<MountInfo> {
-    let mut filtered: Vec<MountInfo> = vec![];
-    let mut seen: HashMap<u64, usize> = HashMap::new();
-    for me in list.into_iter() {
-        let mut discard_me: Option<usize> = None; //
-                                                  // skip
-        if (me.is_remote() && options.show_local_fs)
-            || (me.is_dummy() && !options.show_all_fs && !options.listed_fs.contains(&me.fs_type))
-            || (!options.listed_fs.is_empty() && !options.listed_fs.contains(&me.fs_type))
-            || options.excluded_fs.contains(&me.fs_type)
-        {
-            // pass
-        } else {
-            /*
-            在Linux中有一个bind mount的概念，能把一个目录挂载到另一个目录下，例如mount -o bind /boot/efi /tmp/bindmount
-            在df的输出中我们希望去除重复的设备
-             */
-            if let Some(&idx) = seen.get(&me.dev()) {
-                let seen_dev: &MountInfo = &filtered[idx];
-
-                // target指当前me
-                // source指
-                let target_nearer_root = seen_dev.mount_point.len() > me.mount_point.len();
-                let source_below_root = !seen_dev.root.is_empty()
-                    && !me.root.is_empty()
-                    && (seen_dev.root.len() < me.root.len());
-                if (!options.print_grand_total
-                    && me.is_remote()
-                    && seen_dev.is_remote()
-                    && seen_dev.mount_source.eq(&me.mount_source))
-                {
-                    // don't discard
-                } else if (me.mount_source.contains('/') && !seen_dev.mount_source.contains('/'))
-                    || (target_nearer_root && !source_below_root)
-                    || (!seen_dev.mount_source.eq(&me.mount_source)
-                        && seen_dev.mount_point.eq(&me.mount_point))
-                {
-                    // discard this one
-                    continue;
-                } else {
-                    discard_me = Some(idx);
-                }
-            }
+fn watch(options: &Options) -> Result<(), MountInfoError> {
+    let sysroot = Sysroot::new(None, options.pid);
+    let mut warnings = vec![];
+    let classifier = FsClassifier::load(&sysroot, &mut warnings);
+    for warning in warnings {
+        eprintln!("df: warning: {}", warning);
+    }
+    let filter = options.filter();
+    let stdout = io::stdout();
+    watch::watch_mounts(&sysroot.mount_source(), |events, skipped| {
+        for warning in skipped {
+            eprintln!("df: warning: {}", warning);
         }
-        if let Some(discard_idx) = discard_me {
-            std::mem::replace(&mut filtered[discard_idx], me);
-        } else {
-            let dev = me.dev();
-            filtered.push(me);
-            seen.insert(dev, filtered.len() - 1);
+        let now = timestamp(SystemTime::now());
+        let mut out = stdout.lock();
+        for event in events {
+            if filter.matches(event.mount(), &classifier)
+                && writeln!(out, "{} {}", now, event).is_err()
+            {
+                // stdout is closed
+                return false;
+            }
         }
+        out.flush().is_ok()
+    })
+}
+
+/**
+ * like df.c, 1 when a file system could not be read or none is shown. with nothing
+ * shown and no error printed, say so
+ */
+fn exit_status(rows: usize, failed: bool) -> (i32, Option<&'static str>) {
+    match (rows, failed) {
+        (0, false) => (1, Some("no file systems processed")),
+        (_, true) => (1, None),
+        _ => (0, None),
     }
-    filtered
 }
 
 fn main() {
     let options = parse_args();
-    let table = get_all_entries(&options);
-    if table.is_empty() {
-        println!("no file systems processed");
+    if options.watch_mounts {
+        if let Err(e) = watch(&options) {
+            eprintln!("df: {}", e);
+            process::exit(1);
+        }
         return;
     }
-    show_table(&options, &table);
+    let (table, depths, failed) = match get_all_entries(&options) {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("df: {}", e);
+            process::exit(1);
+        }
+    };
+    if !table.is_empty() {
+        if options.json {
+            show_json(&options, &table, &depths);
+        } else {
+            show_table(&options, &table, &depths);
+        }
+    }
+    let (status, message) = exit_status(table.len(), failed);
+    if let Some(message) = message {
+        eprintln!("df: {}", message);
+    }
+    if status != 0 {
+        process::exit(status);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::blocksize::BlockSize;
+    use crate::cli::Options;
+    use crate::{displayed_fields, exit_status, options_to_fields, renamed_fields};
+
+    #[test]
+    fn test_portability_fields() {
+        let posix = || Options {
+            portability: true,
+            ..Options::new()
+        };
+        assert_eq!(
+            options_to_fields(&posix()),
+            ["Filesystem", "1K-blocks", "Used", "Avail", "Use%", "Mounted on"]
+        );
+        // no File column, unlike the default
+        assert!(options_to_fields(&Options::new()).contains(&"File".to_string()));
+        // -Pi shows inodes, like coreutils
+        let posix_inodes = Options {
+            inodes: true,
+            ..posix()
+        };
+        assert_eq!(
+            options_to_fields(&posix_inodes),
+            ["Filesystem", "Inodes", "IUsed", "IFree", "IUse%", "Mounted on"]
+        );
+
+        assert_eq!(
+            renamed_fields(&posix()),
+            [
+                ("1K-blocks", "1024-blocks".to_string()),
+                ("Avail", "Available".to_string()),
+                ("Use%", "Capacity".to_string()),
+            ]
+        );
+        let posix_512 = Options {
+            block_size: BlockSize::blocks(512),
+            ..posix()
+        };
+        assert_eq!(
+            displayed_fields(&posix_512),
+            ["Filesystem", "512-blocks", "Used", "Available", "Capacity", "Mounted on"]
+        );
+        // without -P only the size column is renamed
+        assert_eq!(
+            renamed_fields(&Options::new()),
+            [("1K-blocks", "1K-blocks".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_exit_status() {
+        assert_eq!(exit_status(3, false), (0, None));
+        assert_eq!(exit_status(3, true), (1, None));
+        assert_eq!(exit_status(0, false), (1, Some("no file systems processed")));
+        // the error is already printed
+        assert_eq!(exit_status(0, true), (1, None));
+    }
 }
@@ -1,40 +1,161 @@
+use std::error::Error;
+use std::ffi::OsString;
+use std::fmt;
 use std::fs;
+use std::io;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
 
-#[derive(Debug, Default)]
+use crate::fsstat::io_error_desc;
+use crate::mountopts::{MountFlags, SuperOptions};
+use nix::sys::stat::{major, makedev, minor};
+
+#[derive(Debug, Clone, Default)]
 pub struct MountInfo {
     pub mount_id: u32,
     pub parent_mount_id: u32,
     pub major_dev: u32,
     pub minor_dev: u32,
-    pub root: String,
-    pub mount_point: String,
-    pub mount_options: Vec<String>,
-    pub optional_fields: Option<Vec<String>>,
+    pub root: PathBuf,
+    pub mount_point: PathBuf,
+    pub mount_options: MountFlags,
+    pub optional_fields: Option<Vec<Propagation>>,
     pub fs_type: String,
-    pub mount_source: String,
-    pub super_options: Vec<String>,
+    pub mount_source: OsString,
+    pub super_options: SuperOptions,
+}
+
+/**
+ * an optional field of mountinfo, see mount_namespaces(7)
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Propagation {
+    /// shared:N, in peer group N
+    Shared(u32),
+    /// master:N, slave of peer group N
+    Master(u32),
+    /// propagate_from:N, receives from peer group N, the nearest dominant one visible
+    PropagateFrom(u32),
+    /// unbindable
+    Unbindable,
+    /// a tag this program doesn't know about
+    Unknown(String),
+}
+
+impl Propagation {
+    fn parse(lineno: usize, field: &[u8]) -> Result<Propagation, MountInfoError> {
+        let text = String::from_utf8_lossy(field);
+        let (tag, value) = match text.split_once(':') {
+            Some((tag, value)) => (tag, Some(value)),
+            None => (text.as_ref(), None),
+        };
+        let group = |name: &'static str| match value {
+            Some(value) => parse_number(lineno, name, value.as_bytes()),
+            None => Err(MountInfoError::InvalidNumber {
+                line: lineno,
+                field: name,
+                value: String::new(),
+            }),
+        };
+        Ok(match tag {
+            "shared" => Propagation::Shared(group("peer group")?),
+            "master" => Propagation::Master(group("master peer group")?),
+            "propagate_from" => Propagation::PropagateFrom(group("propagate_from peer group")?),
+            "unbindable" if value.is_none() => Propagation::Unbindable,
+            _ => Propagation::Unknown(text.into_owned()),
+        })
+    }
+}
+
+impl fmt::Display for Propagation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Propagation::Shared(n) => write!(f, "shared:{}", n),
+            Propagation::Master(n) => write!(f, "master:{}", n),
+            Propagation::PropagateFrom(n) => write!(f, "propagate_from:{}", n),
+            Propagation::Unbindable => write!(f, "unbindable"),
+            Propagation::Unknown(s) => write!(f, "{}", s),
+        }
+    }
 }
 
 impl MountInfo {
+    /**
+     * whether `opt` is set, either as a per-mount flag (e.g. ro, noexec)
+     * or as a superblock option, given as a key or key=value (e.g. errors=remount-ro)
+     */
+    pub fn has_option(&self, opt: &str) -> bool {
+        self.mount_options.has(opt) || self.super_options.matches(opt)
+    }
+
+    pub fn propagation(&self) -> &[Propagation] {
+        self.optional_fields.as_deref().unwrap_or(&[])
+    }
+
+    /**
+     * propagation tags like "shared:2,master:1", "private" when there is none
+     */
+    pub fn propagation_string(&self) -> String {
+        let tags: Vec<String> = self.propagation().iter().map(|x| x.to_string()).collect();
+        if tags.is_empty() {
+            return "private".to_string();
+        }
+        tags.join(",")
+    }
+
+    /**
+     * whether the mount has propagation type `kind`:
+     * shared, slave (or master), private (neither shared nor slave) or unbindable
+     */
+    pub fn has_propagation(&self, kind: &str) -> bool {
+        let shared = self
+            .propagation()
+            .iter()
+            .any(|x| matches!(x, Propagation::Shared(_)));
+        let slave = self
+            .propagation()
+            .iter()
+            .any(|x| matches!(x, Propagation::Master(_)));
+        match kind {
+            "shared" => shared,
+            "slave" | "master" => slave,
+            "private" => !shared && !slave,
+            "unbindable" => self.propagation().contains(&Propagation::Unbindable),
+            _ => false,
+        }
+    }
+
+    /**
+     * the kernel never hands out 0:0, a mount table without device numbers
+     * (e.g. /proc/mounts of another host) leaves it there
+     */
+    pub fn dev_known(&self) -> bool {
+        self.major_dev != 0 || self.minor_dev != 0
+    }
+
+    /**
+     * the dev_t of the mounted filesystem, encoded like glibc's makedev
+     * (12 bit major and 20 bit minor split around the old 8:8 layout)
+     */
     pub fn dev(&self) -> u64 {
-        // refer to linux/kdev_t.h
-        ((self.major_dev as u64) << 8) | self.minor_dev as u64
+        makedev(self.major_dev as u64, self.minor_dev as u64)
     }
     /**
      * refer to mountlist.c:read_file_system_list for detailed explaination
      */
     pub fn is_remote(&self) -> bool {
-        self.mount_source.contains(':')
-            || (self.mount_source.starts_with("//")
-                && (&["smbfs", "smb3", "cifs"])
-                    .into_iter()
-                    .any(|x| x.eq(&self.fs_type)))
-            || (&["afs", "auristorfs"])
-                .into_iter()
-                .any(|x| x.eq(&self.fs_type))
-            || self.mount_source.eq("-hosts")
+        let source = self.mount_source.as_bytes();
+        source.contains(&b':')
+            || (source.starts_with(b"//")
+                && ["smbfs", "smb3", "cifs"].iter().any(|x| x.eq(&self.fs_type)))
+            || ["afs", "auristorfs"].iter().any(|x| x.eq(&self.fs_type))
+            || source == b"-hosts"
     }
 
+    /**
+     * coreutils' ME_DUMMY list. see fsclass::FsClassifier for the complete check
+     */
     pub fn is_dummy(&self) -> bool {
         let dummy_fs_type = [
             "autofs",
@@ -43,6 +164,7 @@ impl MountInfo {
             "debugfs",
             "devpts",
             "fusectl",
+            "fuse.portal",
             "mqueue",
             "rpc_pipefs",
             "sysfs",
@@ -51,54 +173,328 @@ impl MountInfo {
             "ignore",
             "none",
         ];
-        (&dummy_fs_type).into_iter().any(|x| x.eq(&self.fs_type))
+        dummy_fs_type.iter().any(|x| x.eq(&self.fs_type))
     }
 }
 
 /**
- * parse the option string. options are seperated by comma
+ * decode the octal escapes (\040, \011, \012, \134) the kernel uses for
+ * space, tab, newline and backslash in mountinfo fields.
+ * malformed escapes are kept as they are.
  */
-fn parse_options(options: &str) -> Vec<String> {
-    let v: Vec<String> = options.split(",").map(|s| s.to_string()).collect();
-    v
+pub fn unescape(bytes: &[u8]) -> OsString {
+    let mut decoded: Vec<u8> = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'\\'
+            && i + 3 < bytes.len()
+            && bytes[i + 1..i + 4].iter().all(|b| (b'0'..=b'7').contains(b))
+        {
+            let value = (bytes[i + 1] - b'0') as u32 * 64
+                + (bytes[i + 2] - b'0') as u32 * 8
+                + (bytes[i + 3] - b'0') as u32;
+            if value <= 0xff {
+                decoded.push(value as u8);
+                i += 4;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+    OsString::from_vec(decoded)
+}
+
+/**
+ * what to do with a line that can not be parsed
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseMode {
+    /// stop at the first bad line
+    Strict,
+    /// skip bad lines, telling about them in the warnings
+    Lenient,
+}
+
+#[derive(Debug)]
+pub enum MountInfoError {
+    /// the mount table itself can not be read
+    Io { path: PathBuf, source: io::Error },
+    /// the line ends before the named field
+    MissingField { line: usize, field: &'static str },
+    /// a numeric field can not be parsed
+    InvalidNumber {
+        line: usize,
+        field: &'static str,
+        value: String,
+    },
+    /// the device field is not in major:minor form
+    InvalidDevice { line: usize, value: String },
+    /// the "-" between the optional fields and the fs type is missing
+    MissingSeparator { line: usize },
+}
+
+impl fmt::Display for MountInfoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MountInfoError::Io { path, source } => {
+                write!(f, "cannot read {}: {}", path.display(), io_error_desc(source))
+            }
+            MountInfoError::MissingField { line, field } => {
+                write!(f, "line {}: missing {}", line, field)
+            }
+            MountInfoError::InvalidNumber { line, field, value } => {
+                write!(f, "line {}: invalid {} '{}'", line, field, value)
+            }
+            MountInfoError::InvalidDevice { line, value } => {
+                write!(f, "line {}: invalid device number '{}'", line, value)
+            }
+            MountInfoError::MissingSeparator { line } => {
+                write!(f, "line {}: missing '-' separator", line)
+            }
+        }
+    }
+}
+
+impl Error for MountInfoError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            MountInfoError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/**
+ * where the mount table is read from
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MountSource {
+    /// a file in the /proc/PID/mountinfo format
+    MountInfo(PathBuf),
+    /// a file in the fstab-like format of /proc/mounts and /etc/mtab
+    Mounts(PathBuf),
+    /// a mount table of either format, e.g. one captured from another host
+    File(PathBuf),
+}
+
+impl Default for MountSource {
+    fn default() -> MountSource {
+        MountSource::MountInfo(PathBuf::from("/proc/self/mountinfo"))
+    }
+}
+
+/**
+ * format of a mount table file
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TableFormat {
+    MountInfo,
+    Mounts,
+}
+
+impl MountSource {
+    pub fn proc_mounts() -> MountSource {
+        MountSource::Mounts(PathBuf::from("/proc/mounts"))
+    }
+
+    pub fn mtab() -> MountSource {
+        MountSource::Mounts(PathBuf::from("/etc/mtab"))
+    }
+
+    /**
+     * the source of a mount table file given by the user. the tables of this host,
+     * /proc/self/mountinfo, /proc/mounts and /etc/mtab, are read as what they are,
+     * anything else as a File of maybe another host
+     */
+    pub fn from_file(path: &Path) -> MountSource {
+        let canonical = |path: &Path| fs::canonicalize(path).ok();
+        let host_tables = [MountSource::default(), MountSource::proc_mounts(), MountSource::mtab()];
+        let host_table = host_tables.iter().find(|source| {
+            source.path() == path
+                || canonical(path).is_some_and(|x| canonical(source.path()) == Some(x))
+        });
+        match host_table {
+            Some(MountSource::MountInfo(_)) => MountSource::MountInfo(path.to_path_buf()),
+            Some(_) => MountSource::Mounts(path.to_path_buf()),
+            None => MountSource::File(path.to_path_buf()),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        match self {
+            MountSource::MountInfo(path) | MountSource::Mounts(path) | MountSource::File(path) => {
+                path
+            }
+        }
+    }
+
+    /**
+     * read and parse the mount table, lines skipped by a lenient parse go to `warnings`
+     */
+    pub fn read(
+        &self,
+        mode: ParseMode,
+        warnings: &mut Vec<String>,
+    ) -> Result<Vec<MountInfo>, MountInfoError> {
+        let content = fs::read(self.path()).map_err(|e| MountInfoError::Io {
+            path: self.path().to_path_buf(),
+            source: e,
+        })?;
+        let format = match self {
+            MountSource::MountInfo(_) => TableFormat::MountInfo,
+            MountSource::Mounts(_) => TableFormat::Mounts,
+            MountSource::File(_) => detect_format(&content),
+        };
+        let name = self.path().to_string_lossy();
+        let mut list = parse_mount_table(&content, format, &name, mode, warnings)?;
+        if let MountSource::Mounts(_) = self {
+            // fstab-like tables carry no device number, stat the mount point like df.c does.
+            // a table in a File may come from another host, its mount points are not ours to stat
+            for mnt in list.iter_mut() {
+                if let Ok(meta) = fs::metadata(&mnt.mount_point) {
+                    mnt.major_dev = major(meta.dev()) as u32;
+                    mnt.minor_dev = minor(meta.dev()) as u32;
+                }
+            }
+        }
+        Ok(list)
+    }
+}
+
+/**
+ * a mountinfo line starts with "ID PARENT_ID MAJOR:MINOR"
+ */
+fn detect_format(content: &[u8]) -> TableFormat {
+    let first = content
+        .split(|&b| b == b'\n')
+        .find(|line| !line.iter().all(u8::is_ascii_whitespace));
+    if let Some(line) = first {
+        let v: Vec<&[u8]> = line.split(|&b| b == b' ').collect();
+        if v.len() > 2
+            && v[..2].iter().all(|x| !x.is_empty() && x.iter().all(u8::is_ascii_digit))
+            && v[2].contains(&b':')
+        {
+            return TableFormat::MountInfo;
+        }
+    }
+    TableFormat::Mounts
+}
+
+/**
+ * read the mount table from `source`. when the default /proc/self/mountinfo can
+ * not be read (e.g. procfs without mountinfo), fall back to /proc/mounts and /etc/mtab
+ */
+pub fn get_mountinfo_list(
+    source: &MountSource,
+    mode: ParseMode,
+    warnings: &mut Vec<String>,
+) -> Result<Vec<MountInfo>, MountInfoError> {
+    match source.read(mode, warnings) {
+        Err(e @ MountInfoError::Io { .. }) if *source == MountSource::default() => {
+            MountSource::proc_mounts()
+                .read(mode, warnings)
+                .or_else(|_| MountSource::mtab().read(mode, warnings))
+                .map_err(|_| e)
+        }
+        result => result,
+    }
 }
 
-pub fn get_mountinfo_list() -> Vec<MountInfo> {
+/**
+ * parse the whole content of a mount table.
+ * a lenient parse pushes "NAME: line N: ..., skipped" to `warnings` for every bad line
+ */
+pub fn parse_mount_table(
+    content: &[u8],
+    format: TableFormat,
+    name: &str,
+    mode: ParseMode,
+    warnings: &mut Vec<String>,
+) -> Result<Vec<MountInfo>, MountInfoError> {
     let mut list: Vec<MountInfo> = vec![];
-    let content = fs::read_to_string("/proc/self/mountinfo")
-        .expect("Error reading mountinfo at /proc/self/mountinfo");
-    for line in content.split("\n") {
-        if !line.trim().is_empty() {
-            let mnt = parse_mountinfo(line);
-            list.push(mnt);
+    for (i, line) in content.split(|&b| b == b'\n').enumerate() {
+        if line.iter().all(u8::is_ascii_whitespace) {
+            continue;
+        }
+        let parsed = match format {
+            TableFormat::MountInfo => parse_mountinfo_line(i + 1, line),
+            TableFormat::Mounts => parse_mounts_line(i + 1, line).map(|mut mnt| {
+                // no ids in this format, number the mounts in table order
+                mnt.mount_id = (i + 1) as u32;
+                mnt
+            }),
+        };
+        match parsed {
+            Ok(mnt) => list.push(mnt),
+            Err(e) => {
+                if mode == ParseMode::Strict {
+                    return Err(e);
+                }
+                warnings.push(format!("{}: {}, skipped", name, e));
+            }
         }
     }
-    list
+    Ok(list)
+}
+
+fn parse_number(lineno: usize, field: &'static str, value: &[u8]) -> Result<u32, MountInfoError> {
+    let value = String::from_utf8_lossy(value);
+    value.parse().map_err(|_| MountInfoError::InvalidNumber {
+        line: lineno,
+        field,
+        value: value.into_owned(),
+    })
+}
+
+/**
+ * parse a single line of mountinfo, its errors are at line 1
+ */
+pub fn parse_mountinfo<T: AsRef<[u8]>>(line: T) -> Result<MountInfo, MountInfoError> {
+    parse_mountinfo_line(1, line.as_ref())
 }
 
 /**
- * parse a line of mountinfo
+ * parse line `lineno` of a mountinfo table
  */
-pub fn parse_mountinfo(line: &str) -> MountInfo {
-    let v: Vec<&str> = line.split(" ").collect();
-    let mut mnt = MountInfo::default();
-    mnt.mount_id = v[0].parse().unwrap();
-    mnt.parent_mount_id = v[1].parse().unwrap();
+fn parse_mountinfo_line(lineno: usize, line: &[u8]) -> Result<MountInfo, MountInfoError> {
+    let v: Vec<&[u8]> = line.split(|&b| b == b' ').collect();
+    let field = |idx: usize, name: &'static str| {
+        v.get(idx).copied().ok_or(MountInfoError::MissingField {
+            line: lineno,
+            field: name,
+        })
+    };
 
     // dev num
-    let dev: Vec<&str> = v[2].split(":").collect();
+    let dev_field = field(2, "device number")?;
+    let dev: Vec<&[u8]> = dev_field.split(|&b| b == b':').collect();
+    if dev.len() != 2 {
+        return Err(MountInfoError::InvalidDevice {
+            line: lineno,
+            value: String::from_utf8_lossy(dev_field).into_owned(),
+        });
+    }
 
-    mnt.major_dev = dev[0].parse::<u32>().unwrap();
-    mnt.minor_dev = dev[1].parse::<u32>().unwrap();
-    mnt.root = v[3].to_string();
-    mnt.mount_point = v[4].to_string();
-    mnt.mount_options = parse_options(v[5]);
+    let mut mnt = MountInfo {
+        mount_id: parse_number(lineno, "mount ID", field(0, "mount ID")?)?,
+        parent_mount_id: parse_number(lineno, "parent ID", field(1, "parent ID")?)?,
+        major_dev: parse_number(lineno, "major device number", dev[0])?,
+        minor_dev: parse_number(lineno, "minor device number", dev[1])?,
+        root: PathBuf::from(unescape(field(3, "root")?)),
+        mount_point: PathBuf::from(unescape(field(4, "mount point")?)),
+        mount_options: MountFlags::parse(&String::from_utf8_lossy(field(5, "mount options")?)),
+        ..Default::default()
+    };
     let mut next_idx: usize = 6;
-    let mut fields: Vec<String> = vec![];
-    while next_idx < v.len() && v[next_idx].ne("-") {
-        fields.push(v[next_idx].to_string());
+    let mut fields: Vec<Propagation> = vec![];
+    while next_idx < v.len() && v[next_idx] != b"-" {
+        fields.push(Propagation::parse(lineno, v[next_idx])?);
         next_idx += 1;
     }
+    if next_idx == v.len() {
+        return Err(MountInfoError::MissingSeparator { line: lineno });
+    }
 
     if !fields.is_empty() {
         mnt.optional_fields = Some(fields);
@@ -106,45 +502,97 @@ pub fn parse_mountinfo(line: &str) -> MountInfo {
 
     // skip hyphen
     next_idx += 1;
-    if next_idx + 2 >= v.len() {
-        panic!("incomplete mountinfo line");
-    }
 
-    mnt.fs_type = v[next_idx].to_string();
+    mnt.fs_type = String::from_utf8_lossy(field(next_idx, "filesystem type")?).into_owned();
     next_idx += 1;
-    mnt.mount_source = v[next_idx].to_string();
+    mnt.mount_source = unescape(field(next_idx, "mount source")?);
     next_idx += 1;
-    mnt.super_options = parse_options(v[next_idx]);
-    mnt
+    mnt.super_options =
+        SuperOptions::parse(&String::from_utf8_lossy(field(next_idx, "super options")?));
+    Ok(mnt)
+}
+
+/**
+ * parse a line of /proc/mounts or /etc/mtab:
+ * SOURCE TARGET FSTYPE OPTIONS [FREQ PASSNO]
+ * there is no device number or root in this format, and the per-mount and
+ * per-superblock options are mixed. both mount_options and super_options get them all.
+ */
+pub fn parse_mounts<T: AsRef<[u8]>>(line: T) -> Result<MountInfo, MountInfoError> {
+    parse_mounts_line(1, line.as_ref())
+}
+
+/**
+ * parse line `lineno` of a /proc/mounts or /etc/mtab table
+ */
+fn parse_mounts_line(lineno: usize, line: &[u8]) -> Result<MountInfo, MountInfoError> {
+    let v: Vec<&[u8]> = line
+        .split(|b| b.is_ascii_whitespace())
+        .filter(|x| !x.is_empty())
+        .collect();
+    let field = |idx: usize, name: &'static str| {
+        v.get(idx).copied().ok_or(MountInfoError::MissingField {
+            line: lineno,
+            field: name,
+        })
+    };
+    let options = String::from_utf8_lossy(field(3, "mount options")?);
+    Ok(MountInfo {
+        mount_source: unescape(field(0, "mount source")?),
+        mount_point: PathBuf::from(unescape(field(1, "mount point")?)),
+        fs_type: String::from_utf8_lossy(field(2, "filesystem type")?).into_owned(),
+        mount_options: MountFlags::parse(&options),
+        super_options: SuperOptions::parse(&options),
+        ..Default::default()
+    })
+}
+
+/**
+ * a mount table of mountinfo lines, for the tests
+ */
+#[cfg(test)]
+pub(crate) fn table(lines: &[&str]) -> Vec<MountInfo> {
+    lines.iter().map(|x| parse_mountinfo(x).unwrap()).collect()
 }
 
 #[cfg(test)]
 mod tests {
     use crate::mountinfo::get_mountinfo_list;
+    use crate::mountinfo::parse_mount_table;
+    use crate::mountinfo::parse_mounts;
     use crate::mountinfo::parse_mountinfo;
+    use crate::mountinfo::MountInfoError;
+    use crate::mountinfo::MountSource;
+    use crate::mountinfo::ParseMode;
+    use crate::mountinfo::Propagation;
+    use crate::mountinfo::TableFormat;
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::{Path, PathBuf};
 
     #[test]
     fn test_parse_mountinfo() {
         let line = "26 29 0:5 / /dev rw,nosuid,relatime shared:2 - devtmpfs dev rw,size=7631200k,nr_inodes=1907800,mode=755,inode64";
-        let mntinfo = parse_mountinfo(line);
+        let mntinfo = parse_mountinfo(line).unwrap();
         assert_eq!(mntinfo.mount_id, 26);
         assert_eq!(mntinfo.parent_mount_id, 29);
         assert_eq!(mntinfo.major_dev, 0);
         assert_eq!(mntinfo.minor_dev, 5);
-        assert_eq!(mntinfo.root, "/");
-        assert_eq!(mntinfo.mount_point, "/dev");
-        assert_eq!(mntinfo.mount_options, ["rw", "nosuid", "relatime"]);
-        assert_eq!(mntinfo.optional_fields, Some(vec!["shared:2".to_string()]));
+        assert_eq!(mntinfo.root, Path::new("/"));
+        assert_eq!(mntinfo.mount_point, Path::new("/dev"));
+        assert_eq!(mntinfo.mount_options.to_string(), "rw,nosuid,relatime");
+        assert!(mntinfo.mount_options.nosuid);
+        assert_eq!(mntinfo.optional_fields, Some(vec![Propagation::Shared(2)]));
         assert_eq!(mntinfo.fs_type, "devtmpfs");
         assert_eq!(mntinfo.mount_source, "dev");
         assert_eq!(
-            mntinfo.super_options,
+            mntinfo.super_options.iter().collect::<Vec<_>>(),
             [
-                "rw",
-                "size=7631200k",
-                "nr_inodes=1907800",
-                "mode=755",
-                "inode64"
+                ("rw", None),
+                ("size", Some("7631200k")),
+                ("nr_inodes", Some("1907800")),
+                ("mode", Some("755")),
+                ("inode64", None)
             ]
         );
         println!("{:?}", mntinfo);
@@ -153,34 +601,310 @@ mod tests {
     #[test]
     fn test_no_optional_fields() {
         let line = "26 29 0:5 / /dev rw,nosuid,relatime - devtmpfs dev rw,size=7631200k,nr_inodes=1907800,mode=755,inode64";
-        let mntinfo = parse_mountinfo(line);
+        let mntinfo = parse_mountinfo(line).unwrap();
         assert_eq!(mntinfo.mount_id, 26);
         assert_eq!(mntinfo.parent_mount_id, 29);
         assert_eq!(mntinfo.major_dev, 0);
         assert_eq!(mntinfo.minor_dev, 5);
-        assert_eq!(mntinfo.root, "/");
-        assert_eq!(mntinfo.mount_point, "/dev");
-        assert_eq!(mntinfo.mount_options, ["rw", "nosuid", "relatime"]);
+        assert_eq!(mntinfo.root, Path::new("/"));
+        assert_eq!(mntinfo.mount_point, Path::new("/dev"));
+        assert_eq!(mntinfo.mount_options.to_string(), "rw,nosuid,relatime");
+        assert!(mntinfo.mount_options.nosuid);
         assert!(mntinfo.optional_fields.is_none());
         assert_eq!(mntinfo.fs_type, "devtmpfs");
         assert_eq!(mntinfo.mount_source, "dev");
         assert_eq!(
-            mntinfo.super_options,
+            mntinfo.super_options.iter().collect::<Vec<_>>(),
             [
-                "rw",
-                "size=7631200k",
-                "nr_inodes=1907800",
-                "mode=755",
-                "inode64"
+                ("rw", None),
+                ("size", Some("7631200k")),
+                ("nr_inodes", Some("1907800")),
+                ("mode", Some("755")),
+                ("inode64", None)
             ]
         );
         println!("{:?}", mntinfo);
     }
 
+    #[test]
+    fn test_octal_escapes() {
+        let line = r"98 29 8:17 /my\134dir /mnt/My\040Disk rw,relatime shared:50 - ext4 /dev/disk\011by\012label rw";
+        let mntinfo = parse_mountinfo(line).unwrap();
+        assert_eq!(mntinfo.root, Path::new("/my\\dir"));
+        assert_eq!(mntinfo.mount_point, Path::new("/mnt/My Disk"));
+        assert_eq!(mntinfo.mount_source, "/dev/disk\tby\nlabel");
+        assert_eq!(mntinfo.fs_type, "ext4");
+    }
+
+    #[test]
+    fn test_octal_escapes_non_utf8() {
+        let line = r"99 29 8:18 / /mnt/caf\351 rw,relatime - vfat /dev/sdb1 rw";
+        let mntinfo = parse_mountinfo(line).unwrap();
+        assert_eq!(
+            mntinfo.mount_point.as_os_str(),
+            OsStr::from_bytes(b"/mnt/caf\xe9")
+        );
+    }
+
+    #[test]
+    fn test_malformed_escapes_kept() {
+        let line = r"100 29 0:52 / /mnt/a\04 rw - tmpfs tmp\9x rw";
+        let mntinfo = parse_mountinfo(line).unwrap();
+        assert_eq!(mntinfo.mount_point, Path::new(r"/mnt/a\04"));
+        assert_eq!(mntinfo.mount_source, r"tmp\9x");
+    }
+
+    #[test]
+    fn test_propagation() {
+        let line = "40 26 0:50 / /mnt/a rw shared:5 master:1 propagate_from:3 unbindable - tmpfs tmpfs rw";
+        let mntinfo = parse_mountinfo(line).unwrap();
+        assert_eq!(
+            mntinfo.propagation(),
+            [
+                Propagation::Shared(5),
+                Propagation::Master(1),
+                Propagation::PropagateFrom(3),
+                Propagation::Unbindable
+            ]
+        );
+        assert_eq!(
+            mntinfo.propagation_string(),
+            "shared:5,master:1,propagate_from:3,unbindable"
+        );
+        assert!(mntinfo.has_propagation("shared"));
+        assert!(mntinfo.has_propagation("slave"));
+        assert!(mntinfo.has_propagation("unbindable"));
+        assert!(!mntinfo.has_propagation("private"));
+
+        let line = "41 26 0:51 / /mnt/b rw master:7 - tmpfs tmpfs rw";
+        let mntinfo = parse_mountinfo(line).unwrap();
+        assert!(!mntinfo.has_propagation("shared"));
+        assert!(mntinfo.has_propagation("master"));
+
+        let line = "42 26 0:52 / /mnt/c rw - tmpfs tmpfs rw";
+        let mntinfo = parse_mountinfo(line).unwrap();
+        assert_eq!(mntinfo.propagation_string(), "private");
+        assert!(mntinfo.has_propagation("private"));
+
+        // tags from newer kernels are kept
+        let line = "43 26 0:53 / /mnt/d rw shared:1 future:9 - tmpfs tmpfs rw";
+        let mntinfo = parse_mountinfo(line).unwrap();
+        assert_eq!(mntinfo.propagation()[1], Propagation::Unknown("future:9".to_string()));
+
+        let line = "44 26 0:54 / /mnt/e rw shared:x - tmpfs tmpfs rw";
+        assert!(matches!(
+            parse_mountinfo(line),
+            Err(MountInfoError::InvalidNumber {
+                field: "peer group",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_dev() {
+        let dev = |line: &str| parse_mountinfo(line).unwrap().dev();
+        assert_eq!(dev("26 1 8:1 / / rw - ext4 /dev/sda1 rw"), 0x801);
+        // minors above 255 used to collide with the next major
+        let nvme = dev("27 26 259:65536 / /a rw - ext4 /dev/nvme1n1p1 rw");
+        let loop_256 = dev("28 26 7:256 / /b rw - squashfs /dev/loop256 rw");
+        let loop_8 = dev("29 26 8:0 / /c rw - ext4 /dev/sda rw");
+        assert_eq!(nvme, 0x1001_0300);
+        assert_eq!(loop_256, 0x10_0700);
+        assert_ne!(loop_256, loop_8);
+        assert_eq!(loop_256, nix::libc::makedev(7, 256));
+        assert_eq!(nvme, nix::libc::makedev(259, 65536));
+        // anonymous devices go up to 0:1048575
+        assert_eq!(
+            dev("30 26 0:1048575 / /d rw - tmpfs tmpfs rw"),
+            nix::libc::makedev(0, 1048575)
+        );
+    }
+
+    #[test]
+    fn test_invalid_number() {
+        let line = "2x 29 0:5 / /dev rw - devtmpfs dev rw";
+        match parse_mountinfo(line) {
+            Err(MountInfoError::InvalidNumber { field, value, .. }) => {
+                assert_eq!(field, "mount ID");
+                assert_eq!(value, "2x");
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+        let line = "26 29 0-5 / /dev rw - devtmpfs dev rw";
+        assert!(matches!(
+            parse_mountinfo(line),
+            Err(MountInfoError::InvalidDevice { .. })
+        ));
+    }
+
+    #[test]
+    fn test_incomplete_line() {
+        let line = "26 29 0:5 / /dev rw shared:2 devtmpfs dev rw";
+        assert!(matches!(
+            parse_mountinfo(line),
+            Err(MountInfoError::MissingSeparator { line: 1 })
+        ));
+        let line = "26 29 0:5 / /dev rw - devtmpfs dev";
+        assert!(matches!(
+            parse_mountinfo(line),
+            Err(MountInfoError::MissingField {
+                field: "super options",
+                ..
+            })
+        ));
+        let line = "26 29";
+        assert!(matches!(
+            parse_mountinfo(line),
+            Err(MountInfoError::MissingField { .. })
+        ));
+    }
+
+    #[test]
+    fn test_parse_mode() {
+        let content = "\
+26 29 0:5 / /dev rw,nosuid shared:2 - devtmpfs dev rw
+27 26 0:6 / /dev/pts rw - devpts
+28 29 8:1 / / rw - ext4 /dev/sda1 rw
+";
+        let err = parse_mount_table(
+            content.as_bytes(),
+            TableFormat::MountInfo,
+            "fixture",
+            ParseMode::Strict,
+            &mut vec![],
+        )
+        .unwrap_err();
+        assert!(matches!(
+            err,
+            MountInfoError::MissingField {
+                line: 2,
+                field: "mount source"
+            }
+        ));
+        assert_eq!(err.to_string(), "line 2: missing mount source");
+
+        let mut warnings = vec![];
+        let list = parse_mount_table(
+            content.as_bytes(),
+            TableFormat::MountInfo,
+            "fixture",
+            ParseMode::Lenient,
+            &mut warnings,
+        )
+        .unwrap();
+        assert_eq!(warnings, ["fixture: line 2: missing mount source, skipped"]);
+        assert_eq!(list.len(), 2);
+        assert_eq!(list[0].mount_id, 26);
+        assert_eq!(list[1].mount_id, 28);
+
+        let mounts = "proc /proc proc rw 0 0\n\nsysfs /sys\n";
+        let err = parse_mount_table(
+            mounts.as_bytes(),
+            TableFormat::Mounts,
+            "fixture",
+            ParseMode::Strict,
+            &mut vec![],
+        )
+        .unwrap_err();
+        assert_eq!(err.to_string(), "line 3: missing mount options");
+    }
+
+    #[test]
+    fn test_parse_mounts() {
+        let line = r"/dev/sdb1 /mnt/My\040Disk ext4 rw,relatime 0 0";
+        let mntinfo = parse_mounts(line).unwrap();
+        assert_eq!(mntinfo.mount_source, "/dev/sdb1");
+        assert_eq!(mntinfo.mount_point, Path::new("/mnt/My Disk"));
+        assert_eq!(mntinfo.fs_type, "ext4");
+        assert_eq!(mntinfo.mount_options.to_string(), "rw,relatime");
+        assert!(mntinfo.super_options.contains("relatime"));
+
+        // dump and pass are optional
+        assert!(parse_mounts("tmpfs /tmp tmpfs rw").is_ok());
+        assert!(matches!(
+            parse_mounts("tmpfs /tmp tmpfs"),
+            Err(MountInfoError::MissingField {
+                field: "mount options",
+                ..
+            })
+        ));
+    }
+
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures")
+            .join(name)
+    }
+
+    #[test]
+    fn test_mountinfo_fixture() {
+        let source = MountSource::MountInfo(fixture("mountinfo"));
+        let list = get_mountinfo_list(&source, ParseMode::Strict, &mut vec![]).unwrap();
+        assert_eq!(list.len(), 13);
+        assert_eq!(list[5].mount_point, Path::new("/"));
+        assert_eq!(list[5].mount_source, "/dev/nvme0n1p2");
+        assert_eq!(list[10].mount_point, Path::new("/mnt/My Disk"));
+    }
+
+    #[test]
+    fn test_detect_format() {
+        let list = MountSource::File(fixture("mountinfo"))
+            .read(ParseMode::Strict, &mut vec![])
+            .unwrap();
+        assert_eq!(list.len(), 13);
+        assert_eq!(list[0].mount_id, 21);
+        assert_eq!(list[0].parent_mount_id, 26);
+
+        let list = MountSource::File(fixture("mounts"))
+            .read(ParseMode::Strict, &mut vec![])
+            .unwrap();
+        assert_eq!(list.len(), 8);
+        assert_eq!(list[4].mount_id, 5);
+        assert_eq!(list[4].mount_source, "/dev/nvme0n1p2");
+        assert_eq!(list[6].mount_point, Path::new("/mnt/My Disk"));
+        assert!(list[7].is_remote());
+    }
+
     #[test]
     fn test_mountinfo_list() {
-        let list = get_mountinfo_list();
+        let list =
+            get_mountinfo_list(&MountSource::default(), ParseMode::Strict, &mut vec![]).unwrap();
         println!("{:#?}", list);
         assert!(!list.is_empty());
     }
+
+    #[test]
+    fn test_from_file() {
+        let from = |path: &str| MountSource::from_file(Path::new(path));
+        assert_eq!(from("/proc/mounts"), MountSource::proc_mounts());
+        assert_eq!(from("/etc/mtab"), MountSource::mtab());
+        assert_eq!(from("/proc/self/mountinfo"), MountSource::default());
+        // the same files by another name
+        let own = format!("/proc/{}/mounts", std::process::id());
+        assert_eq!(from(&own), MountSource::Mounts(PathBuf::from(&own)));
+        assert_eq!(
+            from("/proc/self/../self/mountinfo"),
+            MountSource::MountInfo(PathBuf::from("/proc/self/../self/mountinfo"))
+        );
+        let fixture = fixture("mountinfo");
+        assert_eq!(
+            MountSource::from_file(&fixture),
+            MountSource::File(fixture.clone())
+        );
+        assert_eq!(
+            from("/no/such/table"),
+            MountSource::File(PathBuf::from("/no/such/table"))
+        );
+    }
+
+    #[test]
+    fn test_io_error() {
+        let source = MountSource::MountInfo(PathBuf::from("/proc/999999999/mountinfo"));
+        let e = get_mountinfo_list(&source, ParseMode::Strict, &mut vec![]).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "cannot read /proc/999999999/mountinfo: No such file or directory"
+        );
+    }
 }
@@ -0,0 +1,274 @@
+use crate::mountinfo::unescape;
+use std::fmt;
+
+/**
+ * split an option string at the commas.
+ * commas inside double quotes (e.g. selinux context="a:b:c0,c1") don't split
+ */
+fn split_options(options: &str) -> Vec<&str> {
+    let mut parts = vec![];
+    let mut quoted = false;
+    let mut start = 0;
+    for (i, c) in options.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ',' if !quoted => {
+                parts.push(&options[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&options[start..]);
+    parts.into_iter().filter(|x| !x.is_empty()).collect()
+}
+
+/**
+ * the kernel escapes ',' '=' and whitespace inside option values as \ooo
+ */
+fn decode(s: &str) -> String {
+    unescape(s.as_bytes()).to_string_lossy().into_owned()
+}
+
+/**
+ * the other way round: ',' '=' whitespace and '\\' as \ooo, so that the options can be split again.
+ * commas inside double quotes are kept, split_options doesn't split there
+ */
+fn encode(s: &str) -> String {
+    let mut encoded = String::with_capacity(s.len());
+    let mut quoted = false;
+    for c in s.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                encoded.push(c);
+            }
+            ',' if quoted => encoded.push(c),
+            ',' | '=' | '\\' => encoded.push_str(&format!("\\{:03o}", c as u32)),
+            _ if c.is_ascii_whitespace() => encoded.push_str(&format!("\\{:03o}", c as u32)),
+            _ => encoded.push(c),
+        }
+    }
+    encoded
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Atime {
+    /// no atime flag shown, atime is always updated
+    #[default]
+    Strictatime,
+    Relatime,
+    Noatime,
+}
+
+/**
+ * the per-mount flags, the 6th field of mountinfo
+ */
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct MountFlags {
+    pub read_only: bool,
+    pub nosuid: bool,
+    pub nodev: bool,
+    pub noexec: bool,
+    pub atime: Atime,
+    pub nodiratime: bool,
+    /// flags without a field above, e.g. nosymfollow, in the order they were given
+    pub other: Vec<String>,
+}
+
+impl MountFlags {
+    pub fn parse(options: &str) -> MountFlags {
+        let mut flags = MountFlags::default();
+        for opt in split_options(options) {
+            match opt {
+                "ro" => flags.read_only = true,
+                "rw" => flags.read_only = false,
+                "nosuid" => flags.nosuid = true,
+                "nodev" => flags.nodev = true,
+                "noexec" => flags.noexec = true,
+                "relatime" => flags.atime = Atime::Relatime,
+                "noatime" => flags.atime = Atime::Noatime,
+                "strictatime" => flags.atime = Atime::Strictatime,
+                "nodiratime" => flags.nodiratime = true,
+                _ => flags.other.push(decode(opt)),
+            }
+        }
+        flags
+    }
+
+    /**
+     * whether flag `name` is set, e.g. "ro", "nosuid", "exec" or "noatime"
+     */
+    pub fn has(&self, name: &str) -> bool {
+        match name {
+            "ro" => self.read_only,
+            "rw" => !self.read_only,
+            "nosuid" => self.nosuid,
+            "suid" => !self.nosuid,
+            "nodev" => self.nodev,
+            "dev" => !self.nodev,
+            "noexec" => self.noexec,
+            "exec" => !self.noexec,
+            "relatime" => self.atime == Atime::Relatime,
+            "noatime" => self.atime == Atime::Noatime,
+            "strictatime" => self.atime == Atime::Strictatime,
+            "nodiratime" => self.nodiratime,
+            _ => self.other.iter().any(|x| x == name),
+        }
+    }
+}
+
+impl fmt::Display for MountFlags {
+    /**
+     * in the order the kernel shows them
+     */
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut flags: Vec<&str> = vec![if self.read_only { "ro" } else { "rw" }];
+        for (set, name) in [
+            (self.nosuid, "nosuid"),
+            (self.nodev, "nodev"),
+            (self.noexec, "noexec"),
+            (self.atime == Atime::Noatime, "noatime"),
+            (self.nodiratime, "nodiratime"),
+            (self.atime == Atime::Relatime, "relatime"),
+        ] {
+            if set {
+                flags.push(name);
+            }
+        }
+        let other: Vec<String> = self.other.iter().map(|x| encode(x)).collect();
+        flags.extend(other.iter().map(|x| x.as_str()));
+        write!(f, "{}", flags.join(","))
+    }
+}
+
+/**
+ * the per-superblock options, the last field of mountinfo, e.g. size=8117160k or errors=remount-ro.
+ * keys keep their order
+ */
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct SuperOptions {
+    entries: Vec<(String, Option<String>)>,
+}
+
+impl SuperOptions {
+    pub fn parse(options: &str) -> SuperOptions {
+        let entries = split_options(options)
+            .into_iter()
+            .map(|opt| match opt.split_once('=') {
+                Some((key, value)) => (decode(key), Some(decode(value))),
+                None => (decode(opt), None),
+            })
+            .collect();
+        SuperOptions { entries }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, Option<&str>)> {
+        self.entries.iter().map(|(k, v)| (k.as_str(), v.as_deref()))
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.entries.iter().any(|(k, _)| k == key)
+    }
+
+    /**
+     * value of `key`, None if the key is missing or has no value
+     */
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|(k, _)| k == key)
+            .and_then(|(_, v)| v.as_deref())
+    }
+
+    /**
+     * `opt` is either a key or key=value
+     */
+    pub fn matches(&self, opt: &str) -> bool {
+        match opt.split_once('=') {
+            Some((key, value)) => self.get(key) == Some(value),
+            None => self.contains(opt),
+        }
+    }
+}
+
+impl fmt::Display for SuperOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let opts: Vec<String> = self
+            .iter()
+            .map(|(k, v)| match v {
+                Some(v) => format!("{}={}", encode(k), encode(v)),
+                None => encode(k),
+            })
+            .collect();
+        write!(f, "{}", opts.join(","))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mountopts::{split_options, Atime, MountFlags, SuperOptions};
+
+    #[test]
+    fn test_split_options() {
+        assert_eq!(split_options("rw,nosuid"), ["rw", "nosuid"]);
+        assert_eq!(
+            split_options(r#"rw,context="system_u:object_r:container_file_t:s0:c1,c2",size=1k"#),
+            [
+                "rw",
+                r#"context="system_u:object_r:container_file_t:s0:c1,c2""#,
+                "size=1k"
+            ]
+        );
+        assert!(split_options("").is_empty());
+    }
+
+    #[test]
+    fn test_mount_flags() {
+        let flags = MountFlags::parse("ro,nosuid,nodev,noexec,noatime,nosymfollow");
+        assert!(flags.read_only);
+        assert!(flags.nosuid && flags.nodev && flags.noexec);
+        assert_eq!(flags.atime, Atime::Noatime);
+        assert_eq!(flags.other, ["nosymfollow"]);
+        assert!(flags.has("ro") && !flags.has("rw"));
+        assert!(flags.has("nosymfollow") && !flags.has("exec"));
+        assert_eq!(
+            flags.to_string(),
+            "ro,nosuid,nodev,noexec,noatime,nosymfollow"
+        );
+
+        let flags = MountFlags::parse("rw,relatime");
+        assert!(!flags.read_only && flags.has("exec") && flags.has("relatime"));
+        assert_eq!(flags.to_string(), "rw,relatime");
+
+        // no atime flag means strictatime
+        let flags = MountFlags::parse("rw");
+        assert_eq!(flags.atime, Atime::Strictatime);
+        assert_eq!(flags.to_string(), "rw");
+    }
+
+    #[test]
+    fn test_super_options() {
+        let opts = SuperOptions::parse(r"rw,size=8117160k,errors=remount-ro,lowerdir=/a\054/b,key\075x=1");
+        assert!(opts.contains("rw"));
+        assert_eq!(opts.get("rw"), None);
+        assert_eq!(opts.get("size"), Some("8117160k"));
+        assert_eq!(opts.get("lowerdir"), Some("/a,/b"));
+        assert_eq!(opts.get("key=x"), Some("1"));
+        assert!(opts.matches("errors=remount-ro"));
+        assert!(opts.matches("errors"));
+        assert!(!opts.matches("errors=continue"));
+        assert_eq!(opts.iter().count(), 5);
+        // escaped again, it splits the same way
+        assert_eq!(
+            opts.to_string(),
+            r"rw,size=8117160k,errors=remount-ro,lowerdir=/a\054/b,key\075x=1"
+        );
+        assert_eq!(SuperOptions::parse(&opts.to_string()), opts);
+
+        let opts = SuperOptions::parse(r#"context="u:r:t:s0:c1,c2",path=a\040b\134c"#);
+        assert_eq!(opts.get("path"), Some(r"a b\c"));
+        assert_eq!(opts.to_string(), r#"context="u:r:t:s0:c1,c2",path=a\040b\134c"#);
+        assert_eq!(SuperOptions::parse(&opts.to_string()), opts);
+    }
+}
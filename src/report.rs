@@ -0,0 +1,491 @@
+use crate::filter::Filter;
+use crate::fsclass::FsClassifier;
+use crate::fsinfo::FsInfo;
+use crate::fsstat::{StatError, StatErrorKind};
+use crate::locate::locate;
+use crate::mountinfo::{self, MountInfo, MountInfoError, MountSource, ParseMode};
+use crate::sysroot::Sysroot;
+use crate::usage::FsUsage;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/**
+ * what a run of df reads and shows, built like
+ *
+ * Query::new(Filter::new().local(true)).file(Path::new("/home")).jobs(4)
+ *
+ * by default all mounts of this host the filter lets through, stat'ed one at a time
+ */
+#[derive(Debug, Clone)]
+pub struct Query {
+    filter: Filter,
+    files: Vec<PathBuf>,
+    mount_table: Option<PathBuf>,
+    sysroot: Option<PathBuf>,
+    pid: Option<u32>,
+    timeout: Option<Duration>,
+    jobs: usize,
+    sync: bool,
+    as_root: bool,
+    statfs: bool,
+}
+
+/**
+ * a row df shows: the usage of a mount, its statfs when asked for and its mount ID
+ */
+#[derive(Debug, Clone)]
+pub struct Row {
+    pub usage: FsUsage,
+    pub info: Option<FsInfo>,
+    pub mount_id: u32,
+}
+
+/**
+ * something to tell on stderr, df prints it after "df: "
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    /// e.g. a skipped mount table line, df goes on as usual
+    Warning(String),
+    /// e.g. a FILE that was not found, df fails
+    Error(String),
+}
+
+/**
+ * the result of Query::run
+ */
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    /// in the order of the mount table, or of the FILE arguments
+    pub rows: Vec<Row>,
+    /// parent mount ID of every mount ID in the mount table, see tree::tree_order
+    pub parents: HashMap<u32, u32>,
+    /// in the order they happened
+    pub messages: Vec<Message>,
+    /// whether a FILE argument or a file system could not be read
+    pub failed: bool,
+}
+
+impl fmt::Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Message::Warning(msg) => write!(f, "warning: {}", msg),
+            Message::Error(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl Query {
+    pub fn new(filter: Filter) -> Query {
+        Query {
+            filter,
+            files: vec![],
+            mount_table: None,
+            sysroot: None,
+            pid: None,
+            timeout: None,
+            jobs: 1,
+            sync: false,
+            as_root: false,
+            statfs: false,
+        }
+    }
+
+    /// like a FILE argument, only show the mount this file is on (and the others given)
+    pub fn file(mut self, file: &Path) -> Query {
+        self.files.push(file.to_path_buf());
+        self
+    }
+
+    /// like --mount-table, read the mounts from this file
+    pub fn mount_table(mut self, path: Option<&Path>) -> Query {
+        self.mount_table = path.map(Path::to_path_buf);
+        self
+    }
+
+    /// like --sysroot, read /proc and /sys of an offline host under this directory
+    pub fn sysroot(mut self, root: Option<&Path>) -> Query {
+        self.sysroot = root.map(Path::to_path_buf);
+        self
+    }
+
+    /// like --pid, the mounts in the mount namespace of this process
+    pub fn pid(mut self, pid: Option<u32>) -> Query {
+        self.pid = pid;
+        self
+    }
+
+    /// like --timeout, give up on a mount whose statvfs takes longer
+    pub fn timeout(mut self, timeout: Option<Duration>) -> Query {
+        self.timeout = timeout;
+        self
+    }
+
+    /// like --jobs, stat this many mounts at once
+    pub fn jobs(mut self, jobs: usize) -> Query {
+        self.jobs = jobs;
+        self
+    }
+
+    /// like --sync, syncfs each mount before reading its usage
+    pub fn sync(mut self, sync: bool) -> Query {
+        self.sync = sync;
+        self
+    }
+
+    /// like --as-root, count the space reserved for root as available
+    pub fn as_root(mut self, as_root: bool) -> Query {
+        self.as_root = as_root;
+        self
+    }
+
+    /// also statfs every mount, for Row::info
+    pub fn statfs(mut self, statfs: bool) -> Query {
+        self.statfs = statfs;
+        self
+    }
+
+    /**
+     * the sysroot to stat in and the mount table to read
+     */
+    fn source(&self) -> (Sysroot, MountSource) {
+        let sysroot = Sysroot::new(self.sysroot.as_deref(), self.pid);
+        match self.mount_table.as_deref().map(MountSource::from_file) {
+            // this host's own table, e.g. /proc/mounts, is as live as the default one
+            Some(source @ (MountSource::MountInfo(_) | MountSource::Mounts(_))) => {
+                (sysroot, source)
+            }
+            // from maybe another host, its usage is only known from the saved stats of --sysroot
+            Some(source) => (sysroot.foreign_table(), source),
+            None => {
+                let source = sysroot.mount_source();
+                (sysroot, source)
+            }
+        }
+    }
+
+    /**
+     * the rows df shows and what to tell about the mounts left out.
+     * fails only when the mount table can not be read
+     */
+    pub fn run(&self) -> Result<Report, MountInfoError> {
+        let (sysroot, source) = self.source();
+        let mut warnings = vec![];
+        let mountlist = mountinfo::get_mountinfo_list(&source, ParseMode::Lenient, &mut warnings)?;
+        let classifier = FsClassifier::load(&sysroot, &mut warnings);
+        let mut report = Report {
+            parents: mountlist
+                .iter()
+                .map(|mnt| (mnt.mount_id, mnt.parent_mount_id))
+                .collect(),
+            messages: warnings.into_iter().map(Message::Warning).collect(),
+            ..Default::default()
+        };
+
+        // with FILE arguments there is a row for every argument, in argument order,
+        // even when several of them are on the same mount
+        let mut filter = self.filter.clone();
+        let (files, mountlist): (Vec<Option<String>>, Vec<MountInfo>) =
+            match self.files.is_empty() {
+                true => {
+                    let mountlist = filter.dedupe(mountlist, &classifier);
+                    (vec![None; mountlist.len()], mountlist)
+                }
+                false => {
+                    let mut located = vec![];
+                    for file in self.files.iter() {
+                        match locate(&sysroot, &mountlist, file) {
+                            Ok(mount) => located
+                                .push((Some(file.to_string_lossy().into_owned()), mount.clone())),
+                            Err(e) => {
+                                let msg = format!("{}: {}", file.display(), e);
+                                report.messages.push(Message::Error(msg));
+                                report.failed = true;
+                            }
+                        }
+                    }
+                    // a file asked for is shown even on a dummy or empty filesystem
+                    filter = filter.all(true);
+                    located.into_iter().unzip()
+                }
+            };
+
+        // get fs usage, statvfs runs on up to self.jobs threads
+        let (files, mountlist): (Vec<Option<String>>, Vec<MountInfo>) = files
+            .into_iter()
+            .zip(mountlist)
+            .filter(|(_, mnt)| filter.matches(mnt, &classifier))
+            .unzip();
+        let synced = match self.sync {
+            true => sysroot.sync(&mountlist, self.timeout, self.jobs),
+            false => mountlist.iter().map(|_| Ok(())).collect(),
+        };
+        let usages = sysroot.usages(&mountlist, self.timeout, self.jobs);
+        // the second call per mount, only when asked for
+        let infos = match self.statfs {
+            true => sysroot.infos(&mountlist, &usages, self.timeout, self.jobs),
+            false => vec![None; mountlist.len()],
+        };
+        let results = usages.into_iter().zip(infos).zip(synced);
+        for ((mount, ((usage, info), sync)), file) in mountlist.iter().zip(results).zip(files) {
+            let usage = self.get_dev(mount, usage, file, &filter, &sysroot, &mut report);
+            let name = sysroot.mount_name(&mount.mount_point);
+            if let Some(warning) = sync_warning(&sync, &name, usage.is_some()) {
+                report.messages.push(Message::Error(warning));
+            }
+            if let Some(usage) = usage {
+                let usage = match self.as_root {
+                    true => usage.as_root(),
+                    false => usage,
+                };
+                // e.g. a FUSE filesystem that calls itself something else
+                if let Some(info) = info.as_ref().filter(|x| !x.matches_type(&mount.fs_type)) {
+                    report.messages.push(Message::Warning(format!(
+                        "{}: type is {} but the magic number {:#x} is {}",
+                        usage.target,
+                        mount.fs_type,
+                        info.magic,
+                        info.magic_name()
+                    )));
+                }
+                report.rows.push(Row {
+                    usage,
+                    info,
+                    mount_id: mount.mount_id,
+                });
+            }
+        }
+        Ok(report)
+    }
+
+    /**
+     * the row of `mount` from the result of its statvfs, if it is shown.
+     * `file` is the FILE argument on the mount. a failed statvfs is handled as in
+     * stat_failure, its error goes to the report
+     */
+    fn get_dev(
+        &self,
+        mount: &MountInfo,
+        usage: Result<FsUsage, StatError>,
+        file: Option<String>,
+        filter: &Filter,
+        sysroot: &Sysroot,
+        report: &mut Report,
+    ) -> Option<FsUsage> {
+        let mut fs_usage = match usage {
+            Ok(usage) => usage,
+            Err(e) => {
+                let name = file
+                    .clone()
+                    .unwrap_or_else(|| sysroot.mount_name(&mount.mount_point));
+                let mut unknown = FsUsage::new(mount, None);
+                unknown.source = sysroot.source_name(mount);
+                let listing = match self.files.is_empty() {
+                    true => Some(self.filter.shows_all()),
+                    false => None,
+                };
+                let in_pid = sysroot.pid().is_some();
+                let (row, error, failed) = stat_failure(&e, &name, unknown, listing, in_pid);
+                report.messages.extend(error.map(Message::Error));
+                report.failed |= failed;
+                row?
+            }
+        };
+        if let Some(file) = file {
+            fs_usage.file = file;
+        }
+
+        if !filter.shows(&fs_usage) {
+            return None;
+        }
+
+        Some(fs_usage)
+    }
+}
+
+/**
+ * what df.c does when the statvfs of a mount fails with `e`: the row to show if any,
+ * the error to print and whether df fails. `unknown` is the row without usage,
+ * `name` the FILE argument or the mount point, `listing` whether all mounts are listed
+ * (and then whether with -a) rather than those of FILE arguments and `in_pid` whether
+ * --pid is given. EACCES and ENOENT are no failure when listing all mounts: those rows
+ * are left out, or shown without usage with -a
+ */
+fn stat_failure(
+    e: &StatError,
+    name: &str,
+    unknown: FsUsage,
+    listing: Option<bool>,
+    in_pid: bool,
+) -> (Option<FsUsage>, Option<String>, bool) {
+    let error = || Some(format!("{}: {}", name, e));
+    match (e.kind(), listing) {
+        // without the right to look into another process's root, say so and keep the row
+        (StatErrorKind::PermissionDenied, _) if in_pid => (Some(unknown), error(), true),
+        // a hung mount, e.g. NFS with the server gone, keep the row and mark it
+        (StatErrorKind::Timeout, _) => {
+            let row = FsUsage {
+                timed_out: true,
+                ..unknown
+            };
+            (Some(row), error(), true)
+        }
+        // a mount of somebody else, or one gone since the mount table was read
+        (StatErrorKind::PermissionDenied | StatErrorKind::NotFound, Some(show_all)) => {
+            let row = FsUsage {
+                fstype: "-".to_string(),
+                ..unknown
+            };
+            (Some(row).filter(|_| show_all), None, false)
+        }
+        // ESTALE, ELOOP, EIO and the rest
+        _ => (None, error(), true),
+    }
+}
+
+/**
+ * the error for a failed syncfs of a mount, the usage is still read, only maybe
+ * without the latest writes. like for statvfs, nothing is said about a mount whose
+ * row is not shown, e.g. one of another user when listing all mounts
+ */
+fn sync_warning(sync: &Result<(), StatError>, name: &str, shown: bool) -> Option<String> {
+    match sync {
+        Err(e) if shown => Some(format!("{}: cannot sync: {}", name, e)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::filter::Filter;
+    use crate::fsstat::StatError;
+    use crate::mountinfo::MountInfo;
+    use crate::report::{stat_failure, sync_warning, Message, Query};
+    use crate::usage::FsUsage;
+    use nix::errno::Errno;
+    use std::path::{Path, PathBuf};
+
+    fn unknown() -> FsUsage {
+        let mount = MountInfo {
+            mount_point: PathBuf::from("/mnt/x"),
+            fs_type: "fuse.sshfs".to_string(),
+            ..Default::default()
+        };
+        FsUsage::new(&mount, None)
+    }
+
+    #[test]
+    fn test_stat_failure() {
+        let listing = Some(false);
+        let all = Some(true);
+        let file = None;
+        let failure = |errno: Errno, listing: Option<bool>, in_pid: bool| {
+            let (row, error, failed) = stat_failure(
+                &StatError::Errno(errno),
+                "/mnt/x",
+                unknown(),
+                listing,
+                in_pid,
+            );
+            (row.map(|x| x.fstype), error, failed)
+        };
+
+        // somebody else's mount, or one gone: left out silently, kept with -a
+        for errno in [Errno::EACCES, Errno::EPERM, Errno::ENOENT] {
+            assert_eq!(failure(errno, listing, false), (None, None, false));
+            assert_eq!(
+                failure(errno, all, false),
+                (Some("-".to_string()), None, false)
+            );
+        }
+        // asked for by a FILE argument, it is an error
+        assert_eq!(
+            failure(Errno::EACCES, file, false),
+            (None, Some("/mnt/x: Permission denied".to_string()), true)
+        );
+        assert_eq!(
+            failure(Errno::ENOENT, file, false),
+            (
+                None,
+                Some("/mnt/x: No such file or directory".to_string()),
+                true
+            )
+        );
+        // the root of another process
+        assert_eq!(
+            failure(Errno::EACCES, listing, true),
+            (
+                Some("fuse.sshfs".to_string()),
+                Some("/mnt/x: Permission denied".to_string()),
+                true
+            )
+        );
+        // broken mounts are errors even with -a
+        for errno in [Errno::ESTALE, Errno::ELOOP, Errno::EIO] {
+            for listing in [listing, all, file] {
+                assert_eq!(
+                    failure(errno, listing, false),
+                    (None, Some(format!("/mnt/x: {}", errno.desc())), true)
+                );
+            }
+        }
+
+        let (row, error, failed) =
+            stat_failure(&StatError::Timeout, "/mnt/x", unknown(), listing, false);
+        assert!(row.unwrap().timed_out);
+        assert_eq!(error.as_deref(), Some("/mnt/x: timed out"));
+        assert!(failed);
+    }
+
+    #[test]
+    fn test_sync_warning() {
+        let denied = Err(StatError::Errno(Errno::EACCES));
+        assert_eq!(
+            sync_warning(&denied, "/mnt/x", true).as_deref(),
+            Some("/mnt/x: cannot sync: Permission denied")
+        );
+        // left out of the listing, as its statvfs error is
+        assert_eq!(sync_warning(&denied, "/mnt/x", false), None);
+        assert_eq!(
+            sync_warning(&Err(StatError::Timeout), "/mnt/x", false),
+            None
+        );
+        assert_eq!(sync_warning(&Ok(()), "/mnt/x", true), None);
+    }
+
+    #[test]
+    fn test_run_files() {
+        let fixture = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/mountinfo");
+        // a table of maybe another host, the files are only compared with its mount points
+        let report = Query::new(Filter::new())
+            .mount_table(Some(&fixture))
+            .file(Path::new("/sys/kernel"))
+            .file(Path::new("/"))
+            .run()
+            .unwrap();
+        // in argument order, sysfs too as it is asked for
+        let rows: Vec<(&str, &str)> = report
+            .rows
+            .iter()
+            .map(|row| (row.usage.file.as_str(), row.usage.target.as_str()))
+            .collect();
+        assert_eq!(rows, [("/sys/kernel", "/sys"), ("/", "/")]);
+        // nothing saved under a sysroot, the usage is unknown but no error
+        assert!(!report.rows[0].usage.usage_known);
+        assert!(!report.failed);
+        assert_eq!(report.parents.get(&21), Some(&26));
+    }
+
+    #[test]
+    fn test_message() {
+        let warning = Message::Warning("/etc/df.toml: bad line, ignored".to_string());
+        assert_eq!(
+            warning.to_string(),
+            "warning: /etc/df.toml: bad line, ignored"
+        );
+        let error = Message::Error("/mnt/x: Permission denied".to_string());
+        assert_eq!(error.to_string(), "/mnt/x: Permission denied");
+    }
+}
@@ -0,0 +1,508 @@
+use crate::fsinfo::FsInfo;
+use crate::fsstat::{parse_saved_stats, sync_fs, FsStat, StatError};
+use crate::mountinfo::{MountInfo, MountSource};
+use crate::usage::FsUsage;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::Duration;
+
+/**
+ * where /proc and /sys are read from: the live system, or a copy of them
+ * (e.g. the proc/ and sys/ trees of a sosreport) given with --sysroot.
+ * with --pid the mounts are the ones seen by that process, which may live
+ * in another mount namespace.
+ * a mount table given as a file (--mount-table) may come from another host,
+ * its mount points are never stat'ed here
+ */
+#[derive(Debug, Default)]
+pub struct Sysroot {
+    root: Option<PathBuf>,
+    pid: Option<u32>,
+    saved_stats: HashMap<PathBuf, FsStat>,
+    foreign_table: bool, // the mounts are from a table file, not from this machine
+}
+
+impl Sysroot {
+    /**
+     * `root` is None for the live system.
+     * statvfs data saved to ROOT/statvfs is loaded if present, see fsstat::parse_saved_stats
+     */
+    pub fn new(root: Option<&Path>, pid: Option<u32>) -> Sysroot {
+        match root {
+            None => Sysroot {
+                pid,
+                ..Default::default()
+            },
+            Some(root) => Sysroot {
+                root: Some(root.to_path_buf()),
+                pid,
+                saved_stats: fs::read(root.join("statvfs"))
+                    .map(|content| parse_saved_stats(&content))
+                    .unwrap_or_default(),
+                ..Default::default()
+            },
+        }
+    }
+
+    /**
+     * the mounts come from a table file of maybe another host, like --mount-table.
+     * the usage is then only known from saved stats, as on an offline host
+     */
+    pub fn foreign_table(mut self) -> Sysroot {
+        self.foreign_table = true;
+        self
+    }
+
+    pub fn is_live(&self) -> bool {
+        self.root.is_none() && !self.foreign_table
+    }
+
+    pub fn pid(&self) -> Option<u32> {
+        self.pid
+    }
+
+    /**
+     * map an absolute path of the inspected host to a path on this machine
+     */
+    pub fn path<P: AsRef<Path>>(&self, path: P) -> PathBuf {
+        let path = path.as_ref();
+        match &self.root {
+            None => path.to_path_buf(),
+            Some(root) => root.join(path.strip_prefix("/").unwrap_or(path)),
+        }
+    }
+
+    /**
+     * the mount table of the inspected host.
+     * a copied tree may only have some of these files, the first one present is used
+     */
+    pub fn mount_source(&self) -> MountSource {
+        if let Some(pid) = self.pid {
+            let path = self.path(format!("/proc/{}/mountinfo", pid));
+            return match self.is_live() {
+                true => MountSource::MountInfo(path),
+                false => MountSource::File(path),
+            };
+        }
+        if self.is_live() {
+            return MountSource::default();
+        }
+        let candidates: Vec<PathBuf> = [
+            "/proc/self/mountinfo",
+            "/proc/1/mountinfo",
+            "/proc/mounts",
+            "/etc/mtab",
+        ]
+        .iter()
+        .map(|x| self.path(x))
+        .collect();
+        let path = candidates
+            .iter()
+            .find(|x| x.is_file())
+            .unwrap_or(&candidates[0]);
+        MountSource::File(path.clone())
+    }
+
+    /**
+     * path to statvfs for `mount_point`.
+     * another process's mounts are reached through its /proc/PID/root
+     */
+    pub fn stat_path(&self, mount_point: &Path) -> PathBuf {
+        match self.pid {
+            Some(pid) => self
+                .path(format!("/proc/{}/root", pid))
+                .join(mount_point.strip_prefix("/").unwrap_or(mount_point)),
+            None => mount_point.to_path_buf(),
+        }
+    }
+
+    /**
+     * `mount_point` in messages: as it is in the table, with the pid of --pid
+     */
+    pub fn mount_name(&self, mount_point: &Path) -> String {
+        match self.pid {
+            Some(pid) => format!("{} (pid {})", mount_point.display(), pid),
+            None => mount_point.display().to_string(),
+        }
+    }
+
+    /**
+     * saved usage of the filesystem mounted on `mount_point`
+     */
+    pub fn saved_stat(&self, mount_point: &Path) -> Option<FsStat> {
+        self.saved_stats.get(mount_point).copied()
+    }
+
+    /**
+     * block device name from /sys/dev/block/MAJOR:MINOR/uevent, e.g. "/dev/nvme0n1p2"
+     */
+    pub fn device_name(&self, major: u32, minor: u32) -> Option<String> {
+        // this machine's /sys knows nothing of the devices of a foreign table
+        if self.foreign_table && self.root.is_none() {
+            return None;
+        }
+        let uevent = self.path(format!("/sys/dev/block/{}:{}/uevent", major, minor));
+        let content = fs::read_to_string(uevent).ok()?;
+        content
+            .lines()
+            .find_map(|line| line.strip_prefix("DEVNAME="))
+            .map(|name| format!("/dev/{}", name))
+    }
+
+    /**
+     * the mount source of `mount` as df shows it.
+     * the kernel shows the root fs as /dev/root when booted with root=MAJ:MIN
+     */
+    pub fn source_name(&self, mount: &MountInfo) -> String {
+        match self.device_name(mount.major_dev, mount.minor_dev) {
+            Some(name) if mount.mount_source == "/dev/root" => name,
+            _ => mount.mount_source.to_string_lossy().into_owned(),
+        }
+    }
+
+    /**
+     * usage of `mount`: statvfs on the live system, the saved data on an offline host.
+     * the usage is unknown when nothing was saved.
+     * with a `timeout` statvfs runs on a worker, see FsStat::stat_timeout
+     */
+    pub fn usage(
+        &self,
+        mount: &MountInfo,
+        timeout: Option<Duration>,
+    ) -> Result<FsUsage, StatError> {
+        let stat_path = self.stat_path(&mount.mount_point);
+        let stat = match self.is_live() {
+            true => Some(match timeout {
+                Some(timeout) => FsStat::stat_timeout(&stat_path, timeout)?,
+                None => FsStat::stat(&stat_path)?,
+            }),
+            // nothing to stat on an offline host
+            false => self.saved_stat(&mount.mount_point),
+        };
+        let mut usage = FsUsage::new(mount, stat.as_ref());
+        usage.source = self.source_name(mount);
+        Ok(usage)
+    }
+
+    /**
+     * usage of every mount in `mounts`, in the same order, stat'ed by up to `jobs` threads
+     */
+    pub fn usages(
+        &self,
+        mounts: &[MountInfo],
+        timeout: Option<Duration>,
+        jobs: usize,
+    ) -> Vec<Result<FsUsage, StatError>> {
+        parallel_map(mounts, jobs, |mnt| self.usage(mnt, timeout))
+    }
+
+    /**
+     * statfs of every mount in `mounts` on up to `jobs` threads, None where it fails
+     * and on an offline host, which has no statfs saved. `usages` are their usages,
+     * see Sysroot::usages, the statvfs of each is not done again
+     */
+    pub fn infos(
+        &self,
+        mounts: &[MountInfo],
+        usages: &[Result<FsUsage, StatError>],
+        timeout: Option<Duration>,
+        jobs: usize,
+    ) -> Vec<Option<FsInfo>> {
+        let stats: Vec<(&MountInfo, Option<FsStat>)> = mounts
+            .iter()
+            .zip(usages)
+            .map(|(mnt, usage)| (mnt, usage.as_ref().ok().and_then(|x| x.stat)))
+            .collect();
+        parallel_map(&stats, jobs, |(mnt, stat)| match (self.is_live(), stat) {
+            (true, Some(stat)) => {
+                FsInfo::statfs_timeout(&self.stat_path(&mnt.mount_point), stat, timeout).ok()
+            }
+            _ => None,
+        })
+    }
+
+    /**
+     * syncfs every mount in `mounts` on up to `jobs` threads, nothing to do on an offline host
+     */
+    pub fn sync(
+        &self,
+        mounts: &[MountInfo],
+        timeout: Option<Duration>,
+        jobs: usize,
+    ) -> Vec<Result<(), StatError>> {
+        parallel_map(mounts, jobs, |mnt| match self.is_live() {
+            true => sync_fs(&self.stat_path(&mnt.mount_point), timeout),
+            false => Ok(()),
+        })
+    }
+}
+
+/**
+ * `f` of every item, in the same order, on up to `jobs` threads
+ */
+fn parallel_map<T, R, F>(items: &[T], jobs: usize, f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    if jobs <= 1 || items.len() <= 1 {
+        return items.iter().map(f).collect();
+    }
+    let next = AtomicUsize::new(0);
+    let mut results: Vec<Option<R>> = items.iter().map(|_| None).collect();
+    thread::scope(|scope| {
+        let workers: Vec<_> = (0..jobs.min(items.len()))
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut done = vec![];
+                    loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        if i >= items.len() {
+                            return done;
+                        }
+                        done.push((i, f(&items[i])));
+                    }
+                })
+            })
+            .collect();
+        for worker in workers {
+            for (i, result) in worker.join().unwrap() {
+                results[i] = Some(result);
+            }
+        }
+    });
+    results.into_iter().flatten().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fsstat::StatErrorKind;
+    use crate::mountinfo::{MountInfo, MountSource, ParseMode};
+    use crate::sysroot::{parallel_map, Sysroot};
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::sync::{mpsc, Mutex};
+    use std::time::Duration;
+
+    fn bundle(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("df-sysroot-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("proc/self")).unwrap();
+        fs::create_dir_all(dir.join("sys/dev/block/259:2")).unwrap();
+        fs::copy(
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/mountinfo"),
+            dir.join("proc/self/mountinfo"),
+        )
+        .unwrap();
+        fs::write(
+            dir.join("sys/dev/block/259:2/uevent"),
+            "MAJOR=259\nMINOR=2\nDEVNAME=nvme0n1p2\nDEVTYPE=partition\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("statvfs"),
+            "4096 61202533 20454286 17322850 15597568 14689164 14689164 /\n",
+        )
+        .unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_live() {
+        let sysroot = Sysroot::new(None, None);
+        assert!(sysroot.is_live());
+        assert_eq!(sysroot.path("/proc/mounts"), Path::new("/proc/mounts"));
+        assert_eq!(sysroot.mount_source(), MountSource::default());
+        assert_eq!(sysroot.stat_path(Path::new("/home")), Path::new("/home"));
+    }
+
+    #[test]
+    fn test_foreign_table() {
+        let fixture = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/mountinfo");
+        let list = MountSource::File(fixture).read(ParseMode::Strict, &mut vec![]).unwrap();
+        let sysroot = Sysroot::new(None, None).foreign_table();
+        assert!(!sysroot.is_live());
+        // /, /run and the cgroup2 mount exist here too, their usage must not be ours
+        let usages = sysroot.usages(&list, None, 4);
+        for usage in usages.iter() {
+            let usage = usage.as_ref().unwrap();
+            assert!(!usage.usage_known, "{}", usage.target);
+            assert_eq!(usage.size, 0);
+        }
+        assert!(sysroot.infos(&list, &usages, None, 1).iter().all(|x| x.is_none()));
+        assert_eq!(sysroot.device_name(259, 2), None);
+    }
+
+    #[test]
+    fn test_host_table() {
+        // --mount-table /proc/mounts on the live host keeps the usage
+        let source = MountSource::from_file(Path::new("/proc/mounts"));
+        let list = source.read(ParseMode::Strict, &mut vec![]).unwrap();
+        let root: Vec<MountInfo> = list
+            .into_iter()
+            .filter(|mnt| mnt.mount_point == Path::new("/"))
+            .collect();
+        assert!(!root.is_empty());
+        for usage in Sysroot::new(None, None).usages(&root, None, 1) {
+            let usage = usage.unwrap();
+            assert!(usage.usage_known);
+            assert!(usage.size > 0);
+        }
+    }
+
+    #[test]
+    fn test_parallel_map() {
+        let items: Vec<u64> = (0..7).collect();
+        let (done, all_done) = mpsc::channel();
+        let all_done = Mutex::new(all_done);
+        let finished = Mutex::new(vec![]);
+        let doubled = parallel_map(&items, 3, |&i| {
+            if i == 0 {
+                // the first item waits until the other workers have done all the rest
+                let all_done = all_done.lock().unwrap();
+                for _ in 1..items.len() {
+                    all_done
+                        .recv_timeout(Duration::from_secs(10))
+                        .expect("the items are not mapped in parallel");
+                }
+                finished.lock().unwrap().push(i);
+            } else {
+                finished.lock().unwrap().push(i);
+                done.send(()).unwrap();
+            }
+            i * 2
+        });
+        assert_eq!(doubled, [0, 2, 4, 6, 8, 10, 12]);
+        // the first one finishes last but keeps its place
+        let finished = finished.into_inner().unwrap();
+        assert_eq!(finished.len(), items.len());
+        assert_eq!(finished.last(), Some(&0));
+        assert_eq!(parallel_map(&items[..0], 3, |&i| i), Vec::<u64>::new());
+    }
+
+    #[test]
+    fn test_usages_live() {
+        let sysroot = Sysroot::new(None, None);
+        let list = sysroot.mount_source().read(ParseMode::Strict, &mut vec![]).unwrap();
+        // usage changes between two calls, the rows and their errors do not
+        let rows = |jobs: usize| -> Vec<Result<String, String>> {
+            sysroot
+                .usages(&list, Some(Duration::from_secs(5)), jobs)
+                .into_iter()
+                .map(|x| x.map(|u| u.target).map_err(|e| e.to_string()))
+                .collect()
+        };
+        let parallel = rows(4);
+        assert_eq!(parallel.len(), list.len());
+        assert_eq!(parallel, rows(1));
+        let targets = list.iter().map(|x| x.mount_point.to_string_lossy());
+        for (row, target) in parallel.iter().zip(targets) {
+            if let Ok(row) = row {
+                assert_eq!(*row, target);
+            }
+        }
+        // a statfs for every mount stat'ed, none for the others
+        let usages = sysroot.usages(&list, None, 4);
+        let infos = sysroot.infos(&list, &usages, None, 4);
+        assert!(infos.iter().any(Option::is_some));
+        for (usage, info) in usages.iter().zip(infos) {
+            if info.is_some() {
+                assert!(usage.as_ref().unwrap().stat.is_some());
+            }
+        }
+    }
+
+    #[test]
+    fn test_sync() {
+        let mut list = Sysroot::new(None, None)
+            .mount_source()
+            .read(ParseMode::Strict, &mut vec![])
+            .unwrap();
+        list.retain(|mnt| mnt.mount_point == Path::new("/"));
+        list.push(MountInfo {
+            mount_point: PathBuf::from("/no/such/dir"),
+            ..Default::default()
+        });
+        let synced = Sysroot::new(None, None).sync(&list, Some(Duration::from_secs(30)), 2);
+        assert_eq!(synced.len(), 2);
+        assert!(synced[0].is_ok());
+        assert_eq!(synced[1].as_ref().unwrap_err().kind(), StatErrorKind::NotFound);
+        // not this host's mounts, nothing to sync
+        let foreign = Sysroot::new(None, None).foreign_table();
+        assert!(foreign.sync(&list, None, 2).iter().all(|x| x.is_ok()));
+    }
+
+    #[test]
+    fn test_pid() {
+        let sysroot = Sysroot::new(None, Some(42));
+        assert_eq!(
+            sysroot.mount_source(),
+            MountSource::MountInfo(PathBuf::from("/proc/42/mountinfo"))
+        );
+        assert_eq!(
+            sysroot.stat_path(Path::new("/var/lib/data")),
+            Path::new("/proc/42/root/var/lib/data")
+        );
+        assert_eq!(sysroot.stat_path(Path::new("/")), Path::new("/proc/42/root"));
+        assert_eq!(
+            sysroot.mount_name(Path::new("/var/lib/data")),
+            "/var/lib/data (pid 42)"
+        );
+        assert_eq!(Sysroot::new(None, None).mount_name(Path::new("/home")), "/home");
+
+        let sysroot = Sysroot::new(Some(Path::new("/tmp/bundle")), Some(42));
+        assert_eq!(
+            sysroot.mount_source(),
+            MountSource::File(PathBuf::from("/tmp/bundle/proc/42/mountinfo"))
+        );
+    }
+
+    #[test]
+    fn test_pid_self() {
+        let sysroot = Sysroot::new(None, Some(std::process::id()));
+        let list = sysroot.mount_source().read(ParseMode::Strict, &mut vec![]).unwrap();
+        assert!(list
+            .iter()
+            .any(|mnt| sysroot.stat_path(&mnt.mount_point).exists()));
+    }
+
+    #[test]
+    fn test_sysroot() {
+        let dir = bundle("paths");
+        let sysroot = Sysroot::new(Some(&dir), None);
+        assert!(!sysroot.is_live());
+        assert_eq!(sysroot.path("/proc/mounts"), dir.join("proc/mounts"));
+
+        let source = sysroot.mount_source();
+        assert_eq!(source, MountSource::File(dir.join("proc/self/mountinfo")));
+        let list = source.read(ParseMode::Strict, &mut vec![]).unwrap();
+        assert_eq!(list.len(), 13);
+
+        assert_eq!(
+            sysroot.device_name(259, 2).as_deref(),
+            Some("/dev/nvme0n1p2")
+        );
+        assert_eq!(sysroot.device_name(8, 1), None);
+
+        assert_eq!(sysroot.saved_stat(Path::new("/")).unwrap().blocks, 61202533);
+        assert!(sysroot.saved_stat(Path::new("/boot/efi")).is_none());
+
+        let root = sysroot.usage(&list[5], None).unwrap();
+        assert!(root.usage_known);
+        assert_eq!(root.size, 61202533 * 4096);
+        let efi = sysroot.usage(&list[9], None).unwrap();
+        assert!(!efi.usage_known);
+        assert_eq!(efi.target, "/boot/efi");
+
+        let usages = sysroot.usages(&list, None, 4);
+        assert_eq!(usages, sysroot.usages(&list, None, 1));
+        assert_eq!(usages[5].as_ref().unwrap().target, "/");
+        // saved stats but no statfs
+        assert!(sysroot.infos(&list, &usages, None, 4).iter().all(|x| x.is_none()));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
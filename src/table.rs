@@ -1,18 +1,17 @@
 use std::collections::HashMap;
+use std::fmt;
 
+#[cfg(test)]
 macro_rules! lines {
     ($( $x:expr ),* $(,)?) => {
         {
-            let mut v = vec![];
-            $(
-                v.push(($x).to_string());
-            )*
+            let v: Vec<String> = vec![$( ($x).to_string(), )*];
             v.join("\n")
         }
     }
 }
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct Table {
     fields: Vec<String>,
     content: Vec<Vec<String>>,
@@ -21,19 +20,14 @@ pub struct Table {
     align: FieldAlign,
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Default)]
 pub enum FieldAlign {
+    #[default]
     Left,
     Right,
     Center,
 }
 
-impl Default for FieldAlign {
-    fn default() -> FieldAlign {
-        FieldAlign::Left
-    }
-}
-
 impl Table {
     pub fn change_field_name(&mut self, orig: &str, new: &str) {
         for i in 0..self.fields.len() {
@@ -41,6 +35,10 @@ impl Table {
                 self.fields[i] = new.to_owned();
             }
         }
+        // the column keeps its align
+        if let Some(align) = self.aligns.remove(orig) {
+            self.aligns.insert(new.to_owned(), align);
+        }
         self.update_column_width();
     }
 
@@ -50,12 +48,12 @@ impl Table {
         }
 
         for (i,field) in self.fields.iter().enumerate() {
-            self.column_width[i] = std::cmp::max(self.column_width[i], field.len());
+            self.column_width[i] = std::cmp::max(self.column_width[i], Self::width(field));
         }
 
         for content in self.content.iter() {
             for (i, v) in content.iter().enumerate() {
-                self.column_width[i] = std::cmp::max(self.column_width[i], v.len());
+                self.column_width[i] = std::cmp::max(self.column_width[i], Self::width(v));
             }
         }
     }
@@ -63,7 +61,7 @@ impl Table {
     pub fn new<T: AsRef<str>>(fields: &[T]) -> Table {
         Table {
             fields: fields.iter().map(|x| x.as_ref().to_string()).collect(),
-            column_width: fields.iter().map(|x| x.as_ref().len()).collect(),
+            column_width: fields.iter().map(|x| Self::width(x.as_ref())).collect(),
             ..Default::default()
         }
     }
@@ -82,12 +80,34 @@ impl Table {
         }
         let mut r = vec![];
         for (i, x) in row.iter().enumerate() {
-            self.column_width[i] = std::cmp::max(self.column_width[i], x.as_ref().len());
+            self.column_width[i] = std::cmp::max(self.column_width[i], Self::width(x.as_ref()));
             r.push(x.as_ref().to_string());
         }
         self.content.push(r);
     }
 
+    fn field_index(&self, field: &str) -> Option<usize> {
+        self.fields.iter().position(|x| x == field)
+    }
+
+    /**
+     * value of `field` in row `row`
+     */
+    pub fn get(&self, row: usize, field: &str) -> Option<&str> {
+        let idx = self.field_index(field)?;
+        self.content.get(row).map(|r| r[idx].as_str())
+    }
+
+    /**
+     * change the value of `field` in row `row`
+     */
+    pub fn set(&mut self, row: usize, field: &str, value: &str) {
+        if let Some(idx) = self.field_index(field) {
+            self.content[row][idx] = value.to_string();
+            self.update_column_width();
+        }
+    }
+
     /**
      * set align for specific field
      */
@@ -103,10 +123,10 @@ impl Table {
     }
 
     pub fn padding(s: &str, width: usize, align: &FieldAlign) -> String {
-        if width <= s.len() {
+        if width <= Self::width(s) {
             return s.to_string();
         }
-        let delta = width - s.len();
+        let delta = width - Self::width(s);
         match &align {
             FieldAlign::Left => format!("{}{}", s, Self::whitespace(delta)),
             FieldAlign::Right => format!("{}{}", Self::whitespace(delta), s),
@@ -119,6 +139,13 @@ impl Table {
         }
     }
 
+    /**
+     * display width, one column per char
+     */
+    fn width(s: &str) -> usize {
+        s.chars().count()
+    }
+
     pub fn whitespace(n: usize) -> String {
         (0..n).map(|_| ' ').collect::<String>()
     }
@@ -160,7 +187,7 @@ impl Table {
                 align_list[idx],
             ));
         }
-        rows.push(header.join(&" ".to_string()));
+        rows.push(header.join(" "));
 
         // content
         for r in self.content.iter() {
@@ -172,49 +199,21 @@ impl Table {
                     align_list[idx],
                 ));
             }
-            rows.push(row.join(&" ".to_string()));
+            rows.push(row.join(" "));
         }
 
-        rows.join(&"\n".to_string())
+        rows.join("\n")
     }
 }
 
-impl ToString for Table {
-    /*
-    fn to_string(&self) -> String {
-        // get column align
-        let mut align_list: Vec<&FieldAlign> = vec![];
-        for field in self.fields.iter() {
-            align_list.push(self.aligns.get(field).unwrap_or(&self.align));
-        }
-
-        let mut rows: Vec<String> = vec![];
-
-        // first row
-        let mut header: Vec<String> = vec![];
-        for (i, field) in self.fields.iter().enumerate() {
-            header.push(Self::padding(field, self.column_width[i], align_list[i]));
-        }
-        rows.push(header.join(&" ".to_string()));
-
-        // content
-        for r in self.content.iter() {
-            let mut row = vec![];
-            for (i, field) in r.iter().enumerate() {
-                row.push(Self::padding(field, self.column_width[i], align_list[i]));
-            }
-            rows.push(row.join(&" ".to_string()));
-        }
-
-        rows.join(&"\n".to_string())
-    }
-     */
-    fn to_string(&self) -> String {
-        self.to_string_partial(&self.fields)
+impl fmt::Display for Table {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_string_partial(&self.fields))
     }
 }
 
 #[cfg(test)]
+#[allow(clippy::assertions_on_constants, clippy::useless_vec)]
 mod tests {
     use crate::table;
     use crate::table::FieldAlign;
@@ -293,6 +292,22 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_get_set() {
+        let mut table = Table::new(&["target", "size"]);
+        table.set_field_align("size", FieldAlign::Right);
+        table.add_row(&["/", "10"]);
+        table.add_row(&["/boot", "1"]);
+        assert_eq!(table.get(1, "target"), Some("/boot"));
+        assert_eq!(table.get(2, "target"), None);
+        assert_eq!(table.get(0, "used"), None);
+        table.set(1, "target", "└─/boot");
+        assert_eq!(
+            table.to_string(),
+            lines!("target  size", "/         10", "└─/boot    1")
+        );
+    }
+
     #[test]
     fn test_lines() {
         assert_eq!("a\nb\nc\nd", lines!("a", "b", "c", "d"));
@@ -0,0 +1,123 @@
+use std::collections::{HashMap, HashSet};
+
+/**
+ * order the mounts `ids` as a tree built from mount ID and parent mount ID:
+ * parents before their children, siblings in mount table order.
+ *
+ * `parents` maps mount ID to parent mount ID for the whole mount table, so a mount
+ * whose parent is not shown is hung under its nearest shown ancestor.
+ * returns (index into ids, depth) for every mount
+ */
+pub fn tree_order(ids: &[u32], parents: &HashMap<u32, u32>) -> Vec<(usize, usize)> {
+    let shown: HashMap<u32, usize> = ids.iter().enumerate().map(|(i, &id)| (id, i)).collect();
+    let mut children: Vec<Vec<usize>> = vec![vec![]; ids.len()];
+    let mut roots: Vec<usize> = vec![];
+    for (i, &id) in ids.iter().enumerate() {
+        let mut visited: HashSet<u32> = HashSet::new();
+        visited.insert(id);
+        let mut parent: Option<usize> = None;
+        let mut current = id;
+        // the root of a namespace may be its own parent, or have a parent we can't see
+        while let Some(&p) = parents.get(&current) {
+            if !visited.insert(p) {
+                break;
+            }
+            if let Some(&idx) = shown.get(&p) {
+                parent = Some(idx);
+                break;
+            }
+            current = p;
+        }
+        match parent {
+            Some(p) => children[p].push(i),
+            None => roots.push(i),
+        }
+    }
+
+    let mut order: Vec<(usize, usize)> = vec![];
+    let mut done = vec![false; ids.len()];
+    let mut walk = |root: usize, order: &mut Vec<(usize, usize)>| {
+        let mut stack = vec![(root, 0)];
+        while let Some((i, depth)) = stack.pop() {
+            if done[i] {
+                continue;
+            }
+            done[i] = true;
+            order.push((i, depth));
+            for &child in children[i].iter().rev() {
+                stack.push((child, depth + 1));
+            }
+        }
+    };
+    for &root in roots.iter() {
+        walk(root, &mut order);
+    }
+    // mounts caught in a parent loop are not reachable from any root, show them anyway
+    for i in 0..ids.len() {
+        walk(i, &mut order);
+    }
+    order
+}
+
+/**
+ * findmnt-like tree art for rows in tree order, e.g. "│ ├─"
+ */
+pub fn tree_prefixes(depths: &[usize]) -> Vec<String> {
+    // whether another row at the same depth follows under the same parent
+    let has_next_sibling: Vec<bool> = (0..depths.len())
+        .map(|i| {
+            depths[i + 1..]
+                .iter()
+                .find(|&&d| d <= depths[i])
+                .is_some_and(|&d| d == depths[i])
+        })
+        .collect();
+
+    let mut prefixes = vec![];
+    let mut ancestors: Vec<usize> = vec![];
+    for (i, &depth) in depths.iter().enumerate() {
+        ancestors.truncate(depth);
+        let mut prefix = String::new();
+        if depth > 0 {
+            for &a in ancestors[1..].iter() {
+                prefix.push_str(if has_next_sibling[a] { "│ " } else { "  " });
+            }
+            prefix.push_str(if has_next_sibling[i] { "├─" } else { "└─" });
+        }
+        prefixes.push(prefix);
+        ancestors.push(i);
+    }
+    prefixes
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tree::{tree_order, tree_prefixes};
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_tree_order() {
+        // 1 is the root, 5 is hidden so 6 goes under 1, 9 has no known parent
+        let parents: HashMap<u32, u32> = [(1, 0), (2, 1), (3, 2), (4, 1), (5, 1), (6, 5), (9, 8)]
+            .iter()
+            .copied()
+            .collect();
+        let ids = [2, 1, 3, 4, 6, 9];
+        let order = tree_order(&ids, &parents);
+        assert_eq!(order, [(1, 0), (0, 1), (2, 2), (3, 1), (4, 1), (5, 0)]);
+    }
+
+    #[test]
+    fn test_tree_loop() {
+        let parents: HashMap<u32, u32> = [(1, 1), (2, 3), (3, 2)].iter().copied().collect();
+        let order = tree_order(&[1, 2, 3], &parents);
+        assert_eq!(order.len(), 3);
+        assert_eq!(order[0], (0, 0));
+    }
+
+    #[test]
+    fn test_tree_prefixes() {
+        let prefixes = tree_prefixes(&[0, 1, 2, 2, 1, 2, 0]);
+        assert_eq!(prefixes, ["", "├─", "│ ├─", "│ └─", "└─", "  └─", ""]);
+    }
+}
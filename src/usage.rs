@@ -0,0 +1,327 @@
+use crate::fsstat::FsStat;
+use crate::mountinfo::MountInfo;
+
+/**
+ * one row of df: a mounted filesystem and how much of it is used.
+ * sizes are in bytes, the string fields are "-" when unknown
+ */
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct FsUsage {
+    pub source: String,
+    pub fstype: String,
+    pub file: String,
+    pub target: String,
+    pub itotal: u64,
+    pub iused: i64, // negative when more inodes are free than there are
+    pub iavail: u64,
+    pub ipcent: Option<i32>, // None when there are no inodes or iused is negative
+    pub size: i128,
+    pub used: i128,          // negative when more blocks are free than there are
+    pub avail: i128,         // negative when the reserved blocks are eaten into
+    pub pcent: Option<i32>,  // None when used + avail is 0 or it is negative
+    pub reserved: i128,      // free but only for root
+    pub rpcent: Option<i32>, // reserved / size, None when the size is 0
+    pub majmin: String,
+    pub propagation: String,
+    pub opts: String,
+    pub sopts: String,
+    pub usage_known: bool, // false when the usage of an offline host was not saved
+    pub timed_out: bool,   // statvfs did not answer in time, the usage is unknown
+    pub stat: Option<FsStat>, // what the usage is from, live or saved
+}
+
+/**
+ * used / (used + avail) in %, rounded up like coreutils.
+ * None when used + avail is 0 or the percentage is negative, df shows "-" for both
+ */
+fn percent_round_up(used: i128, avail: i128) -> Option<i32> {
+    let total = used + avail;
+    if total == 0 {
+        return None;
+    }
+    let (quotient, remainder) = (used * 100 / total, used * 100 % total);
+    // the ceiling, whichever sign the operands have
+    let pcent = match remainder != 0 && (remainder > 0) == (total > 0) {
+        true => quotient + 1,
+        false => quotient,
+    };
+    match pcent < 0 {
+        true => None,
+        false => Some(pcent.min(i32::MAX as i128) as i32),
+    }
+}
+
+fn saturate_i64(x: i128) -> i64 {
+    x.clamp(i64::MIN as i128, i64::MAX as i128) as i64
+}
+
+fn saturate_u64(x: i128) -> u64 {
+    x.clamp(0, u64::MAX as i128) as u64
+}
+
+impl FsUsage {
+    /**
+     * the row of `mount`, with the usage numbers from `stat` if there is one
+     */
+    pub fn new(mount: &MountInfo, stat: Option<&FsStat>) -> FsUsage {
+        let mut fs_usage = FsUsage {
+            source: mount.mount_source.to_string_lossy().into_owned(),
+            fstype: mount.fs_type.clone(),
+            file: "-".to_owned(),
+            target: mount.mount_point.to_string_lossy().into_owned(),
+            majmin: "-".to_owned(),
+            propagation: mount.propagation_string(),
+            opts: mount.mount_options.to_string(),
+            sopts: mount.super_options.to_string(),
+            ..Default::default()
+        };
+        if mount.dev_known() {
+            fs_usage.majmin = format!("{}:{}", mount.major_dev, mount.minor_dev);
+        }
+
+        if let Some(stat) = stat {
+            // 信息读取成功
+            // 128位计算，不会溢出
+            let blocks = stat.blocks as i128;
+            let used = blocks - stat.blocks_free as i128;
+            // like coreutils, an f_bavail with the top bit set is negative:
+            // more is used than there is for users
+            let avail = stat.blocks_available as i64 as i128;
+            // in bytes, the block size of the output is applied when it is shown
+            let fragment_size = stat.fragment_size as i128;
+            fs_usage.size = blocks * fragment_size;
+            fs_usage.used = used * fragment_size;
+            fs_usage.avail = avail * fragment_size;
+            // 不是除size，见coreutils中的df
+            fs_usage.pcent = percent_round_up(used, avail);
+            // blocks_free - blocks_available, the reserve is all of it when avail is negative
+            let reserved = (stat.blocks_free as i128 - avail).max(0);
+            fs_usage.reserved = reserved * fragment_size;
+            fs_usage.rpcent = match blocks != 0 {
+                true => percent_round_up(reserved, blocks - reserved),
+                false => None,
+            };
+            // inode
+            let iused = stat.files as i128 - stat.files_available as i128;
+            fs_usage.itotal = stat.files;
+            fs_usage.iused = saturate_i64(iused);
+            fs_usage.iavail = stat.files_available;
+            fs_usage.ipcent = match stat.files != 0 {
+                true => percent_round_up(iused, stat.files_available as i128),
+                false => None,
+            };
+            fs_usage.usage_known = true;
+            fs_usage.stat = Some(*stat);
+        }
+        fs_usage
+    }
+
+    /**
+     * the total row of --total: sizes and inodes of `usages` summed up and
+     * the percentages of the sums. usages that are not known don't count
+     */
+    pub fn total<'a, I: IntoIterator<Item = &'a FsUsage>>(usages: I) -> FsUsage {
+        let (mut size, mut used, mut avail, mut reserved) = (0i128, 0i128, 0i128, 0i128);
+        let (mut itotal, mut iused, mut iavail) = (0i128, 0i128, 0i128);
+        for usage in usages.into_iter().filter(|x| x.usage_known) {
+            size += usage.size;
+            used += usage.used;
+            avail += usage.avail;
+            reserved += usage.reserved;
+            itotal += usage.itotal as i128;
+            iused += usage.iused as i128;
+            iavail += usage.iavail as i128;
+        }
+        FsUsage {
+            source: "total".to_owned(),
+            fstype: "-".to_owned(),
+            file: "-".to_owned(),
+            target: "-".to_owned(),
+            itotal: saturate_u64(itotal),
+            iused: saturate_i64(iused),
+            iavail: saturate_u64(iavail),
+            ipcent: match itotal != 0 {
+                true => percent_round_up(iused, iavail),
+                false => None,
+            },
+            size,
+            used,
+            avail,
+            pcent: percent_round_up(used, avail),
+            reserved,
+            rpcent: match size != 0 {
+                true => percent_round_up(reserved, size - reserved),
+                false => None,
+            },
+            majmin: "-".to_owned(),
+            propagation: "-".to_owned(),
+            opts: "-".to_owned(),
+            sopts: "-".to_owned(),
+            usage_known: true,
+            timed_out: false,
+            stat: None,
+        }
+    }
+
+    /**
+     * the usage as root sees it: the reserved space is available too
+     */
+    pub fn as_root(mut self) -> FsUsage {
+        if self.usage_known {
+            self.avail += self.reserved;
+            self.pcent = percent_round_up(self.used, self.avail);
+        }
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fsstat::FsStat;
+    use crate::mountinfo::parse_mountinfo;
+    use crate::usage::FsUsage;
+
+    #[test]
+    fn test_usage() {
+        let mount =
+            parse_mountinfo("22 1 8:1 / / rw,relatime shared:1 - ext4 /dev/sda1 rw").unwrap();
+        let stat = FsStat {
+            fragment_size: 4096,
+            blocks: 1000,
+            blocks_free: 300,
+            blocks_available: 250,
+            files: 100,
+            files_free: 40,
+            files_available: 40,
+            ..Default::default()
+        };
+        let usage = FsUsage::new(&mount, Some(&stat));
+        assert_eq!(usage.source, "/dev/sda1");
+        assert_eq!(usage.majmin, "8:1");
+        assert_eq!(
+            (usage.size, usage.used, usage.avail),
+            (4000 << 10, 2800 << 10, 1000 << 10)
+        );
+        // used / (used + avail), rounded up
+        assert_eq!(usage.pcent, Some(74));
+        // 50 of the 300 free blocks are root's
+        assert_eq!((usage.reserved, usage.rpcent), (200 << 10, Some(5)));
+        assert_eq!(
+            (usage.itotal, usage.iused, usage.ipcent),
+            (100, 60, Some(60))
+        );
+        assert!(usage.usage_known);
+
+        let root = usage.clone().as_root();
+        assert_eq!(
+            (root.used, root.avail, root.pcent),
+            (2800 << 10, 1200 << 10, Some(70))
+        );
+        assert_eq!(root.reserved, 200 << 10);
+
+        // 512 byte blocks, an odd number of them is not a whole number of 1K blocks
+        let stat = FsStat {
+            fragment_size: 512,
+            blocks: 1001,
+            blocks_free: 3,
+            blocks_available: 1,
+            ..stat
+        };
+        let usage = FsUsage::new(&mount, Some(&stat));
+        assert_eq!(
+            (usage.size, usage.used, usage.avail, usage.reserved),
+            (512512, 510976, 512, 1024)
+        );
+
+        let usage = FsUsage::new(&mount, None);
+        assert!(!usage.usage_known);
+        assert_eq!(usage.clone().as_root(), usage);
+        assert_eq!(usage.size, 0);
+        assert_eq!(usage.target, "/");
+    }
+
+    #[test]
+    fn test_total() {
+        let mount =
+            parse_mountinfo("22 1 8:1 / / rw,relatime shared:1 - ext4 /dev/sda1 rw").unwrap();
+        let stat = |blocks, blocks_free, blocks_available, files, files_available| FsStat {
+            fragment_size: 1024,
+            blocks,
+            blocks_free,
+            blocks_available,
+            files,
+            files_free: files_available,
+            files_available,
+            ..Default::default()
+        };
+        let usages = [
+            FsUsage::new(&mount, Some(&stat(1000, 300, 250, 100, 40))),
+            FsUsage::new(&mount, Some(&stat(3000, 1000, 1000, 0, 0))),
+            // not known, left out
+            FsUsage::new(&mount, None),
+        ];
+        let total = FsUsage::total(&usages);
+        assert_eq!(
+            (total.source.as_str(), total.target.as_str()),
+            ("total", "-")
+        );
+        assert_eq!(
+            (total.size, total.used, total.avail),
+            (4000 << 10, 2700 << 10, 1250 << 10)
+        );
+        assert_eq!(
+            (total.pcent, total.reserved, total.rpcent),
+            (Some(69), 50 << 10, Some(2))
+        );
+        assert_eq!((total.itotal, total.iused, total.iavail), (100, 60, 40));
+        assert_eq!(total.ipcent, Some(60));
+
+        let total = FsUsage::total(&[]);
+        assert!(total.usage_known);
+        assert_eq!((total.size, total.pcent, total.ipcent), (0, None, None));
+    }
+
+    #[test]
+    fn test_usage_extremes() {
+        let mount =
+            parse_mountinfo("22 1 8:1 / / rw,relatime shared:1 - ext4 /dev/sda1 rw").unwrap();
+        let stat = |blocks, blocks_free, blocks_available, files, files_available| FsStat {
+            fragment_size: 4096,
+            blocks,
+            blocks_free,
+            blocks_available,
+            files,
+            files_free: files_available,
+            files_available,
+            ..Default::default()
+        };
+
+        // root's reserve eaten into, avail is negative and Use% over 100
+        let usage = FsUsage::new(&mount, Some(&stat(1000, 10, (-40i64) as u64, 0, 0)));
+        assert_eq!((usage.used, usage.avail), (3960 << 10, -160 << 10));
+        assert_eq!(usage.pcent, Some(105));
+        assert_eq!((usage.reserved, usage.rpcent), (200 << 10, Some(5)));
+        assert_eq!(usage.ipcent, None);
+        assert_eq!(usage.as_root().pcent, Some(99));
+
+        // more free than there is
+        let usage = FsUsage::new(&mount, Some(&stat(10, 20, 20, 5, 8)));
+        assert_eq!((usage.used, usage.avail), (-40 << 10, 80 << 10));
+        assert_eq!(usage.pcent, None);
+        assert_eq!((usage.iused, usage.ipcent), (-3, None));
+
+        // nothing used and nothing available
+        let usage = FsUsage::new(&mount, Some(&stat(0, 0, 0, 0, 0)));
+        assert_eq!((usage.size, usage.pcent), (0, None));
+
+        // too big for 64 bits once in bytes
+        let usage = FsUsage::new(&mount, Some(&stat(u64::MAX, 0, 0, u64::MAX, 0)));
+        assert_eq!(usage.size, u64::MAX as i128 * 4096);
+        assert_eq!(usage.used, u64::MAX as i128 * 4096);
+        assert_eq!(usage.pcent, Some(100));
+        assert_eq!((usage.iused, usage.ipcent), (i64::MAX, Some(100)));
+        let usage = FsUsage::new(&mount, Some(&stat(u64::MAX, u64::MAX, u64::MAX, 1, 1)));
+        assert_eq!((usage.used, usage.avail), (0, -4096));
+        assert_eq!(usage.pcent, Some(0));
+    }
+}
@@ -0,0 +1,320 @@
+use crate::mountinfo::{
+    parse_mount_table, MountInfo, MountInfoError, MountSource, ParseMode, TableFormat,
+};
+use nix::errno::Errno;
+use nix::poll::{poll, PollFd, PollFlags};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::os::unix::io::AsRawFd;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/**
+ * a change between two reads of the mount table, mounts are matched by mount ID
+ */
+#[derive(Debug)]
+pub enum MountEvent<'a> {
+    Mounted(&'a MountInfo),
+    Unmounted(&'a MountInfo),
+    /// options, super options or propagation changed
+    Remounted {
+        old: &'a MountInfo,
+        new: &'a MountInfo,
+    },
+    /// mount --move
+    Moved {
+        old: &'a MountInfo,
+        new: &'a MountInfo,
+    },
+}
+
+impl<'a> MountEvent<'a> {
+    /**
+     * the mount as it is after the event, as it was before for an unmount
+     */
+    pub fn mount(&self) -> &'a MountInfo {
+        match self {
+            MountEvent::Mounted(mount) | MountEvent::Unmounted(mount) => mount,
+            MountEvent::Remounted { new, .. } | MountEvent::Moved { new, .. } => new,
+        }
+    }
+}
+
+/**
+ * "label removed -> added", e.g. "options rw -> ro", None if nothing changed
+ */
+fn describe_change(label: &str, old: &[String], new: &[String]) -> Option<String> {
+    let removed: Vec<&str> = old
+        .iter()
+        .filter(|x| !new.contains(x))
+        .map(|x| x.as_str())
+        .collect();
+    let added: Vec<&str> = new
+        .iter()
+        .filter(|x| !old.contains(x))
+        .map(|x| x.as_str())
+        .collect();
+    if removed.is_empty() && added.is_empty() {
+        return None;
+    }
+    let join = |list: Vec<&str>| match list.is_empty() {
+        true => "-".to_string(),
+        false => list.join(","),
+    };
+    Some(format!("{} {} -> {}", label, join(removed), join(added)))
+}
+
+fn flag_list(mount: &MountInfo) -> Vec<String> {
+    mount
+        .mount_options
+        .to_string()
+        .split(',')
+        .map(|x| x.to_string())
+        .collect()
+}
+
+fn super_option_list(mount: &MountInfo) -> Vec<String> {
+    mount
+        .super_options
+        .iter()
+        .map(|(k, v)| match v {
+            Some(v) => format!("{}={}", k, v),
+            None => k.to_string(),
+        })
+        .collect()
+}
+
+fn propagation_list(mount: &MountInfo) -> Vec<String> {
+    vec![mount.propagation_string()]
+}
+
+/**
+ * what changed between `old` and `new` of the same mount
+ */
+fn remount_changes(old: &MountInfo, new: &MountInfo) -> Vec<String> {
+    [
+        describe_change("options", &flag_list(old), &flag_list(new)),
+        describe_change(
+            "super options",
+            &super_option_list(old),
+            &super_option_list(new),
+        ),
+        describe_change(
+            "propagation",
+            &propagation_list(old),
+            &propagation_list(new),
+        ),
+    ]
+    .iter()
+    .flatten()
+    .cloned()
+    .collect()
+}
+
+/**
+ * events turning mount table `old` into `new`:
+ * unmounts in the order of `old`, then mounts, moves and remounts in the order of `new`
+ */
+pub fn diff_mounts<'a>(old: &'a [MountInfo], new: &'a [MountInfo]) -> Vec<MountEvent<'a>> {
+    let old_by_id: HashMap<u32, &MountInfo> = old.iter().map(|x| (x.mount_id, x)).collect();
+    let new_by_id: HashMap<u32, &MountInfo> = new.iter().map(|x| (x.mount_id, x)).collect();
+    let mut events = vec![];
+    for mount in old.iter() {
+        if !new_by_id.contains_key(&mount.mount_id) {
+            events.push(MountEvent::Unmounted(mount));
+        }
+    }
+    for mount in new.iter() {
+        match old_by_id.get(&mount.mount_id) {
+            None => events.push(MountEvent::Mounted(mount)),
+            Some(&before) => {
+                if before.mount_point != mount.mount_point {
+                    events.push(MountEvent::Moved {
+                        old: before,
+                        new: mount,
+                    });
+                }
+                if !remount_changes(before, mount).is_empty() {
+                    events.push(MountEvent::Remounted {
+                        old: before,
+                        new: mount,
+                    });
+                }
+            }
+        }
+    }
+    events
+}
+
+impl fmt::Display for MountEvent<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mount = self.mount();
+        let what = match self {
+            MountEvent::Mounted(_) => "mounted",
+            MountEvent::Unmounted(_) => "unmounted",
+            MountEvent::Remounted { .. } => "remounted",
+            MountEvent::Moved { .. } => "moved",
+        };
+        write!(
+            f,
+            "{} {} {} {}",
+            what,
+            mount.mount_point.display(),
+            mount.fs_type,
+            mount.mount_source.to_string_lossy()
+        )?;
+        match self {
+            MountEvent::Mounted(mount) => write!(f, " {}", mount.mount_options),
+            MountEvent::Unmounted(_) => Ok(()),
+            MountEvent::Remounted { old, new } => {
+                write!(f, ": {}", remount_changes(old, new).join(", "))
+            }
+            MountEvent::Moved { old, .. } => write!(f, ": from {}", old.mount_point.display()),
+        }
+    }
+}
+
+/**
+ * days since 1970-01-01 to (year, month, day), from Howard Hinnant's civil_from_days
+ */
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/**
+ * UTC time in ISO 8601, e.g. 2021-09-05T13:07:42Z
+ */
+pub fn timestamp(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |x| x.as_secs() as i64);
+    let (year, month, day) = civil_from_days(secs.div_euclid(86400));
+    let secs = secs.rem_euclid(86400);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+fn read_table(
+    file: &mut File,
+    name: &str,
+    warnings: &mut Vec<String>,
+) -> io::Result<Vec<MountInfo>> {
+    let mut content = vec![];
+    file.seek(SeekFrom::Start(0))?;
+    file.read_to_end(&mut content)?;
+    // a lenient parse never fails
+    Ok(parse_mount_table(
+        &content,
+        TableFormat::MountInfo,
+        name,
+        ParseMode::Lenient,
+        warnings,
+    )
+    .unwrap_or_default())
+}
+
+/**
+ * keep the mountinfo file of `source` open and call `handle` with the events of
+ * every change and the lines skipped reading the new table, until it returns false.
+ * lines skipped in the first read come with no events. the kernel flags the file
+ * with POLLPRI whenever the mount table changes
+ */
+pub fn watch_mounts<F>(source: &MountSource, mut handle: F) -> Result<(), MountInfoError>
+where
+    F: FnMut(&[MountEvent], &[String]) -> bool,
+{
+    let path = source.path();
+    let name = path.to_string_lossy();
+    let io_error = |e: io::Error| MountInfoError::Io {
+        path: path.to_path_buf(),
+        source: e,
+    };
+    let mut file = File::open(path).map_err(io_error)?;
+    let mut warnings = vec![];
+    let mut old = read_table(&mut file, &name, &mut warnings).map_err(io_error)?;
+    if !warnings.is_empty() && !handle(&[], &warnings) {
+        return Ok(());
+    }
+    loop {
+        let mut fds = [PollFd::new(file.as_raw_fd(), PollFlags::POLLPRI)];
+        match poll(&mut fds, -1) {
+            Ok(_) => {}
+            Err(Errno::EINTR) => continue,
+            Err(e) => return Err(io_error(e.into())),
+        }
+        let mut warnings = vec![];
+        let new = read_table(&mut file, &name, &mut warnings).map_err(io_error)?;
+        if !handle(&diff_mounts(&old, &new), &warnings) {
+            return Ok(());
+        }
+        old = new;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mountinfo::table;
+    use crate::watch::{diff_mounts, timestamp, MountEvent};
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn test_diff_mounts() {
+        let old = table(&[
+            "22 1 8:1 / / rw,relatime shared:1 - ext4 /dev/sda1 rw,errors=remount-ro",
+            "30 22 8:17 / /mnt/usb rw,nosuid shared:5 - vfat /dev/sdb1 rw,fmask=0022",
+            "31 22 8:33 / /data rw,relatime shared:6 - ext4 /dev/sdc1 rw",
+            "32 22 0:50 / /old rw - tmpfs tmpfs rw",
+        ]);
+        let new = table(&[
+            "22 1 8:1 / / rw,relatime shared:1 - ext4 /dev/sda1 rw,errors=remount-ro",
+            "31 22 8:33 / /data ro,relatime - ext4 /dev/sdc1 ro,errors=remount-ro",
+            "32 22 0:50 / /new rw - tmpfs tmpfs rw",
+            "40 22 0:60 / /mnt/nfs rw shared:9 - nfs4 srv:/export rw,vers=4.2",
+        ]);
+        let events: Vec<String> = diff_mounts(&old, &new)
+            .iter()
+            .map(|x| x.to_string())
+            .collect();
+        assert_eq!(
+            events,
+            [
+                "unmounted /mnt/usb vfat /dev/sdb1",
+                "remounted /data ext4 /dev/sdc1: options rw -> ro, super options rw -> ro,errors=remount-ro, propagation shared:6 -> private",
+                "moved /new tmpfs tmpfs: from /old",
+                "mounted /mnt/nfs nfs4 srv:/export rw",
+            ]
+        );
+        assert!(matches!(diff_mounts(&old, &new)[0], MountEvent::Unmounted(m) if m.mount_id == 30));
+        assert!(diff_mounts(&new, &new).is_empty());
+    }
+
+    #[test]
+    fn test_timestamp() {
+        assert_eq!(timestamp(UNIX_EPOCH), "1970-01-01T00:00:00Z");
+        assert_eq!(
+            timestamp(UNIX_EPOCH + Duration::from_secs(951_782_400 + 3723)),
+            "2000-02-29T01:02:03Z"
+        );
+        assert_eq!(
+            timestamp(UNIX_EPOCH + Duration::from_secs(1_630_847_262)),
+            "2021-09-05T13:07:42Z"
+        );
+    }
+}